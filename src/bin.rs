@@ -1,49 +1,206 @@
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut novel = novelscript::Novel::new();
-
-    let file = std::fs::read_to_string("test2.ns")?;
-    novel.add_scene("test2".into(), &file);
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 
-    let file = std::fs::read_to_string("test2_night.ns")?;
-    novel.add_scene("test2_night".into(), &file);
+const USAGE: &str =
+    "usage: novelscript-bin play <files...> [--start <scene>] [--set <variable>=<value>...]";
 
-    let mut state = novel.new_state("test2");
+const HELP: &str = "commands:
+  <enter>         continue
+  <number>        pick an option of a choice
+  save <file>     save the game
+  load <file>     load a saved game
+  quit            stop playing";
 
-    state.set_variable("number".into(), 1);
-    state.set_variable("another_number".into(), 0);
+struct Options {
+    files: Vec<String>,
+    start: Option<String>,
+    variables: Vec<(String, novelscript::Value)>,
+}
 
-    while let Some(node) = novel.next(&mut state) {
-        match node {
-            novelscript::SceneNodeUser::Data(node) => match node {
-                novelscript::SceneNodeData::Text { speaker, content } => {
-                    println!("{}: {}", speaker.as_ref().unwrap_or(&"*".into()), content)
-                }
-                novelscript::SceneNodeData::Choice(choices) => {
-                    println!("{:?}", choices);
-                    state.set_choice(1);
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    if args.next().as_deref() != Some("play") {
+        return Err("expected the 'play' command".into());
+    }
+    let mut options = Options {
+        files: Vec::new(),
+        start: None,
+        variables: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--start" => {
+                options.start = Some(args.next().ok_or("--start needs a scene name")?);
+            }
+            "--set" => {
+                let assignment = args.next().ok_or("--set needs a variable=value")?;
+                let (name, value) = assignment
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected variable=value, got '{}'", assignment))?;
+                if name == "choice" {
+                    return Err("'choice' is set by picking an option, not with --set".into());
                 }
-            },
-            novelscript::SceneNodeUser::Load(node) => match node {
-                novelscript::SceneNodeLoad::Character {
-                    character,
-                    expression,
-                    placement,
-                } => println!(
-                    "Load {} with {:?} expression at {:?}",
-                    character, expression, placement
-                ),
-                novelscript::SceneNodeLoad::Background { name } => {
-                    println!("Load background {}", name)
+                options
+                    .variables
+                    .push((name.to_owned(), parse_value(value)));
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ => options.files.push(arg),
+        }
+    }
+    if options.files.is_empty() {
+        return Err("no script files were given".into());
+    }
+    Ok(options)
+}
+
+fn parse_value(value: &str) -> novelscript::Value {
+    if let Ok(int) = value.parse::<i32>() {
+        int.into()
+    } else if let Ok(float) = value.parse::<f64>() {
+        float.into()
+    } else if let Ok(boolean) = value.parse::<bool>() {
+        boolean.into()
+    } else {
+        value.into()
+    }
+}
+
+fn scene_name(file: &str) -> Result<String, String> {
+    Path::new(file)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(String::from)
+        .ok_or_else(|| format!("can't make a scene name from '{}'", file))
+}
+
+fn show(node: &novelscript::SceneNodeUser) {
+    match node {
+        novelscript::SceneNodeUser::Data(node) => match node {
+            novelscript::SceneNodeData::Text { speaker, content } => {
+                println!("{}: {}", speaker.as_deref().unwrap_or("*"), content)
+            }
+            novelscript::SceneNodeData::Choice(choices) => {
+                for choice in choices {
+                    let picked = if choice.previously_picked { " *" } else { "" };
+                    println!("  {}) {}{}", choice.index, choice.text, picked);
                 }
-                novelscript::SceneNodeLoad::PlaySound { name, channel } => {
-                    println!("Playing {} on {:?}", name, channel)
+            }
+        },
+        novelscript::SceneNodeUser::Load(node) => match node {
+            novelscript::SceneNodeLoad::Character {
+                character,
+                expression,
+                placement,
+            } => println!(
+                "[Load {} with {:?} expression at {:?}]",
+                character, expression, placement
+            ),
+            novelscript::SceneNodeLoad::Background { name } => {
+                println!("[Load background {}]", name)
+            }
+            novelscript::SceneNodeLoad::PlaySound { name, channel } => {
+                println!("[Playing {} on {}]", name, channel)
+            }
+            novelscript::SceneNodeLoad::RemoveCharacter { name } => {
+                println!("[Removed {}]", name)
+            }
+        },
+    }
+}
+
+fn load(
+    novel: &novelscript::Novel,
+    path: &str,
+) -> Result<novelscript::NovelState, Box<dyn std::error::Error>> {
+    let state = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(novel.load_state(state)?)
+}
+
+fn play(options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    for file in &options.files {
+        let data = std::fs::read_to_string(file)?;
+        novel.add_scene(scene_name(file)?, &data)?;
+    }
+
+    let start = match options.start {
+        Some(start) => start,
+        None => scene_name(&options.files[0])?,
+    };
+    let mut state = novel.new_state(&start);
+    for (name, value) in options.variables {
+        state.set_variable(name, value);
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut node = novel.next(&mut state)?;
+    'play: while let Some(current) = node {
+        show(&current);
+        let choices = match &current {
+            novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(choices)) => {
+                Some(choices)
+            }
+            novelscript::SceneNodeUser::Data(_) => None,
+            novelscript::SceneNodeUser::Load(_) => {
+                node = novel.next(&mut state)?;
+                continue;
+            }
+        };
+
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (None, _) if choices.is_none() => break,
+                (Some("save"), Some(path)) => {
+                    std::fs::write(path, serde_json::to_string(&state)?)?;
+                    println!("Saved to {}", path);
                 }
-                novelscript::SceneNodeLoad::RemoveCharacter { name } => {
-                    println!("Removed {}", name)
+                (Some("load"), Some(path)) => match load(&novel, path) {
+                    Ok(loaded) => {
+                        state = loaded;
+                        node = novel.current(&mut state)?;
+                        continue 'play;
+                    }
+                    Err(err) => println!("Couldn't load {}: {}", path, err),
+                },
+                (Some("quit"), None) => return Ok(()),
+                (Some(number), None) if choices.is_some() => {
+                    let index = number.parse::<i32>().ok();
+                    let choices = choices.unwrap();
+                    match index.filter(|&index| choices.iter().any(|c| c.index == index)) {
+                        Some(index) => {
+                            state.set_choice(index);
+                            break;
+                        }
+                        None => println!("Pick one of the options by its number"),
+                    }
                 }
-            },
+                _ => println!("{}", HELP),
+            }
         }
+        node = novel.next(&mut state)?;
     }
 
     Ok(())
 }
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = play(options) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
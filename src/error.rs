@@ -0,0 +1,173 @@
+use std::{fmt, io, path::PathBuf};
+
+/// Where in a scene's source an error happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub scene: String,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number.
+    pub column: usize,
+    /// The full source line the error points at.
+    pub snippet: String,
+}
+
+impl SourceLocation {
+    pub(crate) fn new(scene: &str, source: &str, pos: usize) -> Self {
+        let pos = pos.min(source.len());
+        let line_start = source[..pos].rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = source[pos..]
+            .find('\n')
+            .map_or(source.len(), |idx| pos + idx);
+        SourceLocation {
+            scene: scene.to_owned(),
+            line: source[..pos].matches('\n').count() + 1,
+            column: source[line_start..pos].chars().count() + 1,
+            snippet: source[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.scene, self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Syntax {
+        location: SourceLocation,
+        message: String,
+    },
+    UnknownLoadProperty {
+        location: SourceLocation,
+        key: String,
+    },
+    UnknownComparison {
+        location: SourceLocation,
+        operator: String,
+    },
+    NumberOutOfRange {
+        location: SourceLocation,
+        number: String,
+    },
+    DuplicateLabel {
+        location: SourceLocation,
+        label: String,
+    },
+    UnknownLabel {
+        location: SourceLocation,
+        label: String,
+    },
+    DuplicateNodeId {
+        location: SourceLocation,
+        id: String,
+    },
+}
+
+impl ParseError {
+    pub fn location(&self) -> &SourceLocation {
+        match self {
+            ParseError::Syntax { location, .. }
+            | ParseError::UnknownLoadProperty { location, .. }
+            | ParseError::UnknownComparison { location, .. }
+            | ParseError::NumberOutOfRange { location, .. }
+            | ParseError::DuplicateLabel { location, .. }
+            | ParseError::UnknownLabel { location, .. }
+            | ParseError::DuplicateNodeId { location, .. } => location,
+        }
+    }
+
+    /// The error description without the location prefix, `Display` puts the location in front of it.
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::Syntax { message, .. } => message.clone(),
+            ParseError::UnknownLoadProperty { key, .. } => {
+                format!("unknown load property key '{}'", key)
+            }
+            ParseError::UnknownComparison { operator, .. } => {
+                format!("unknown comparison operator '{}'", operator)
+            }
+            ParseError::NumberOutOfRange { number, .. } => {
+                format!("number '{}' is out of range", number)
+            }
+            ParseError::DuplicateLabel { label, .. } => {
+                format!("label '{}' is defined more than once", label)
+            }
+            ParseError::UnknownLabel { label, .. } => format!("couldn't find label '{}'", label),
+            ParseError::DuplicateNodeId { id, .. } => {
+                format!("node id '{}' is used more than once", id)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location(), self.message())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum NovelError {
+    #[error("couldn't find scene '{0}'")]
+    SceneNotFound(String),
+    #[error("couldn't read scene '{scene}': {message}")]
+    SceneRead { scene: String, message: String },
+    #[error("{0}")]
+    SceneParse(ParseError),
+    #[error("couldn't find label '{label}' in scene '{scene}'")]
+    LabelNotFound { scene: String, label: String },
+    #[error("the state hasn't been advanced with next yet")]
+    NotStarted,
+    #[error("scope {depth} in scene '{scene}' doesn't point to a valid node")]
+    InvalidScope { scene: String, depth: usize },
+    #[error("variable '{0}' is not defined")]
+    UndefinedVariable(String),
+    #[error("choice {choice} was picked but there are only {options} options")]
+    InvalidChoice { choice: i32, options: usize },
+    #[error("choice {0} is not available")]
+    UnavailableChoice(i32),
+    #[error("can't roll back {steps} steps, only {available} are available")]
+    RollbackTooFar { steps: usize, available: usize },
+    #[error("the saved position in scene '{scene}' points at node '{id}' which no longer exists")]
+    IncompatibleState { scene: String, id: String },
+    #[error("the save has format {found} but only formats up to {supported} are supported")]
+    UnsupportedFormat { found: u32, supported: u32 },
+    #[error(
+        "the save is from version {found} of the scripts which is newer than version {current}"
+    )]
+    NewerVersion { found: u32, current: u32 },
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("invalid text: {0}")]
+    InvalidText(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("{0}")]
+    Parse(#[from] ParseError),
+    #[error("couldn't read '{}': {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("invalid manifest '{}': {message}", path.display())]
+    Manifest { path: PathBuf, message: String },
+    #[error("scene '{0}' is defined more than once")]
+    DuplicateScene(String),
+    #[error(
+        "scene '{0}' can't have a '.' in its name, jumps would read the part after it as a label"
+    )]
+    InvalidSceneName(String),
+    #[error("couldn't find scene '{0}'")]
+    SceneNotFound(String),
+    #[cfg(feature = "zip")]
+    #[error("invalid archive: {0}")]
+    Archive(#[from] zip::result::ZipError),
+}
@@ -0,0 +1,294 @@
+use crate::{value::Literal, NovelError, NovelState, UndefinedVariablePolicy, Value};
+use std::{cmp::Ordering, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOp {
+    /// Ints stay ints, mixing in a float gives a float and strings can be added together.
+    pub fn apply(self, lhs: Value, rhs: Value) -> Result<Value, NovelError> {
+        Ok(match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => Value::Int(match self {
+                BinaryOp::Add => lhs.wrapping_add(rhs),
+                BinaryOp::Subtract => lhs.wrapping_sub(rhs),
+                BinaryOp::Multiply => lhs.wrapping_mul(rhs),
+                BinaryOp::Divide | BinaryOp::Remainder if rhs == 0 => {
+                    return Err(NovelError::DivisionByZero)
+                }
+                BinaryOp::Divide => lhs.wrapping_div(rhs),
+                BinaryOp::Remainder => lhs.wrapping_rem(rhs),
+            }),
+            (Value::String(lhs), Value::String(rhs)) if self == BinaryOp::Add => {
+                Value::String(lhs + &rhs)
+            }
+            (lhs, rhs) => match (lhs.as_float(), rhs.as_float()) {
+                (Some(lhs), Some(rhs)) => Value::Float(match self {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Subtract => lhs - rhs,
+                    BinaryOp::Multiply => lhs * rhs,
+                    BinaryOp::Divide | BinaryOp::Remainder if rhs == 0.0 => {
+                        return Err(NovelError::DivisionByZero)
+                    }
+                    BinaryOp::Divide => lhs / rhs,
+                    BinaryOp::Remainder => lhs % rhs,
+                }),
+                _ => {
+                    return Err(NovelError::TypeMismatch(format!(
+                        "can't use '{}' on {} and {}",
+                        self,
+                        lhs.type_name(),
+                        rhs.type_name()
+                    )))
+                }
+            },
+        })
+    }
+}
+
+/// An arithmetic expression, e.g. `trust * 2 - 1`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Variable(String),
+    Negate(Box<Expr>),
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+impl Expr {
+    pub fn eval(
+        &self,
+        state: &NovelState,
+        policy: UndefinedVariablePolicy,
+    ) -> Result<Value, NovelError> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Variable(name) => match state.script_variable(name) {
+                Some(value) => Ok(value),
+                None => policy.undefined(name),
+            },
+            Expr::Negate(expr) => match expr.eval(state, policy)? {
+                Value::Int(n) => Ok(Value::Int(n.wrapping_neg())),
+                Value::Float(n) => Ok(Value::Float(-n)),
+                value => Err(NovelError::TypeMismatch(format!(
+                    "can't negate {}",
+                    value.type_name()
+                ))),
+            },
+            Expr::Binary { op, lhs, rhs } => {
+                op.apply(lhs.eval(state, policy)?, rhs.eval(state, policy)?)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equals,
+    NotEquals,
+    MoreThan,
+    LessThan,
+    MoreThanOrEquals,
+    LessThanOrEquals,
+}
+
+impl Comparison {
+    pub fn compare(self, first: &Value, second: &Value) -> Result<bool, NovelError> {
+        let ordering = first.try_cmp(second)?;
+        Ok(match self {
+            Comparison::Equals => ordering == Ordering::Equal,
+            Comparison::NotEquals => ordering != Ordering::Equal,
+            Comparison::MoreThan => ordering == Ordering::Greater,
+            Comparison::LessThan => ordering == Ordering::Less,
+            Comparison::MoreThanOrEquals => ordering != Ordering::Less,
+            Comparison::LessThanOrEquals => ordering != Ordering::Greater,
+        })
+    }
+}
+
+/// A condition of an if statement, e.g. `(trust >= 5 and met_alice) or debug`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare {
+        first: Expr,
+        compare: Comparison,
+        second: Expr,
+    },
+    /// A bare expression, true when its value is truthy, see [`Value::is_truthy`].
+    Truthy(Expr),
+    /// `chose id n`, true when option `n` was picked the last time the choice `id` was made.
+    Chose {
+        id: String,
+        choice: i32,
+    },
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Returns the condition that is true exactly when this one is false.
+    pub fn new_reverse(self) -> Self {
+        match self {
+            Condition::Compare {
+                first,
+                compare,
+                second,
+            } => Condition::Compare {
+                first,
+                compare: match compare {
+                    Comparison::Equals => Comparison::NotEquals,
+                    Comparison::NotEquals => Comparison::Equals,
+                    Comparison::MoreThan => Comparison::LessThanOrEquals,
+                    Comparison::LessThan => Comparison::MoreThanOrEquals,
+                    Comparison::MoreThanOrEquals => Comparison::LessThan,
+                    Comparison::LessThanOrEquals => Comparison::MoreThan,
+                },
+                second,
+            },
+            cond @ (Condition::Truthy(_) | Condition::Chose { .. }) => {
+                Condition::Not(Box::new(cond))
+            }
+            Condition::Not(cond) => *cond,
+            Condition::And(first, second) => Condition::Or(
+                Box::new(first.new_reverse()),
+                Box::new(second.new_reverse()),
+            ),
+            Condition::Or(first, second) => Condition::And(
+                Box::new(first.new_reverse()),
+                Box::new(second.new_reverse()),
+            ),
+        }
+    }
+
+    /// Returns the condition that is true when both this one and `other` are.
+    pub fn and(self, other: Condition) -> Self {
+        Condition::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn check(
+        &self,
+        state: &NovelState,
+        policy: UndefinedVariablePolicy,
+    ) -> Result<bool, NovelError> {
+        Ok(match self {
+            Condition::Compare {
+                first,
+                compare,
+                second,
+            } => compare.compare(&first.eval(state, policy)?, &second.eval(state, policy)?)?,
+            Condition::Truthy(expr) => expr.eval(state, policy)?.is_truthy(),
+            Condition::Chose { id, choice } => state.last_choice(id) == Some(*choice),
+            Condition::Not(cond) => !cond.check(state, policy)?,
+            Condition::And(first, second) => {
+                first.check(state, policy)? && second.check(state, policy)?
+            }
+            Condition::Or(first, second) => {
+                first.check(state, policy)? || second.check(state, policy)?
+            }
+        })
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Remainder => "%",
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(value) => write!(f, "{}", Literal(value)),
+            Expr::Variable(name) => f.write_str(name),
+            Expr::Negate(expr) => match **expr {
+                Expr::Binary { .. } => write!(f, "-({})", expr),
+                _ => write!(f, "-{}", expr),
+            },
+            Expr::Binary { op, lhs, rhs } => {
+                lhs.fmt_operand(f)?;
+                write!(f, " {} ", op)?;
+                rhs.fmt_operand(f)
+            }
+        }
+    }
+}
+
+impl Expr {
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Binary { .. } => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Equals => "=",
+            Comparison::NotEquals => "!=",
+            Comparison::MoreThan => ">",
+            Comparison::LessThan => "<",
+            Comparison::MoreThanOrEquals => ">=",
+            Comparison::LessThanOrEquals => "<=",
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Compare {
+                first,
+                compare,
+                second,
+            } => write!(f, "{} {} {}", first, compare, second),
+            Condition::Truthy(expr) => write!(f, "{}", expr),
+            Condition::Chose { id, choice } => write!(f, "chose {} {}", id, choice),
+            Condition::Not(cond) => match **cond {
+                Condition::And(..) | Condition::Or(..) | Condition::Compare { .. } => {
+                    write!(f, "not ({})", cond)
+                }
+                _ => write!(f, "not {}", cond),
+            },
+            Condition::And(first, second) => {
+                first.fmt_operand(f, self)?;
+                f.write_str(" and ")?;
+                second.fmt_operand(f, self)
+            }
+            Condition::Or(first, second) => {
+                first.fmt_operand(f, self)?;
+                f.write_str(" or ")?;
+                second.fmt_operand(f, self)
+            }
+        }
+    }
+}
+
+impl Condition {
+    /// Formats an operand of `parent`, only mixed `and`/`or` get parentheses.
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, parent: &Condition) -> fmt::Result {
+        match (self, parent) {
+            (Condition::And(..), Condition::Or(..)) | (Condition::Or(..), Condition::And(..)) => {
+                write!(f, "({})", self)
+            }
+            _ => write!(f, "{}", self),
+        }
+    }
+}
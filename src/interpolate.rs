@@ -0,0 +1,170 @@
+//! `{variable}` placeholders in dialogue and choice text.
+//!
+//! A placeholder can have a format spec after a colon, `{name:[[fill]align][width][.precision]}`
+//! where align is one of `<`, `>` or `^`, e.g. `{gold:>5}` or `{ratio:.2}`.
+//! A width starting with `0` pads numbers with zeros after their sign, like `{gold:05}`.
+//! `{{` and `}}` are literal braces.
+
+use crate::{NovelError, Value};
+
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder { name: &'a str, spec: FormatSpec },
+}
+
+#[derive(Default)]
+struct FormatSpec {
+    fill: Option<char>,
+    align: Option<char>,
+    width: usize,
+    precision: Option<usize>,
+    /// Set by a leading `0`, numbers are padded with zeros after their sign.
+    zero_pad: bool,
+}
+
+impl FormatSpec {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut format = FormatSpec::default();
+        let mut rest = spec;
+
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(fill), Some(align @ ('<' | '>' | '^'))) => {
+                format.fill = Some(fill);
+                format.align = Some(align);
+                rest = &rest[fill.len_utf8() + 1..];
+            }
+            (Some(align @ ('<' | '>' | '^')), _) => {
+                format.align = Some(align);
+                rest = &rest[1..];
+            }
+            _ => {}
+        }
+        if format.align.is_none() && rest.starts_with('0') {
+            format.zero_pad = true;
+        }
+
+        let (width, precision) = match rest.find('.') {
+            Some(idx) => (&rest[..idx], Some(&rest[idx + 1..])),
+            None => (rest, None),
+        };
+        if !width.is_empty() {
+            format.width = width
+                .parse()
+                .map_err(|_| format!("invalid width in format spec '{}'", spec))?;
+        }
+        if let Some(precision) = precision {
+            format.precision = Some(
+                precision
+                    .parse()
+                    .map_err(|_| format!("invalid precision in format spec '{}'", spec))?,
+            );
+        }
+        Ok(format)
+    }
+
+    fn format(&self, value: &Value) -> String {
+        let formatted = match (value, self.precision) {
+            (Value::Float(n), Some(precision)) => format!("{:.*}", precision, n),
+            (Value::Int(n), Some(precision)) => format!("{:.*}", precision, f64::from(*n)),
+            (Value::String(s), Some(precision)) => s.chars().take(precision).collect(),
+            (value, _) => value.to_string(),
+        };
+
+        let len = formatted.chars().count();
+        if len >= self.width {
+            return formatted;
+        }
+        let padding = self.width - len;
+        if self.zero_pad {
+            let number = matches!(value, Value::Int(_) | Value::Float(_));
+            let sign = if number && formatted.starts_with('-') {
+                1
+            } else {
+                0
+            };
+            let (sign, digits) = formatted.split_at(sign);
+            return format!("{}{}{}", sign, "0".repeat(padding), digits);
+        }
+        let align = self.align.unwrap_or(match value {
+            Value::Int(_) | Value::Float(_) => '>',
+            _ => '<',
+        });
+        let (before, after) = match align {
+            '>' => (padding, 0),
+            '^' => (padding / 2, padding - padding / 2),
+            _ => (0, padding),
+        };
+        let fill = self.fill.unwrap_or(' ');
+        let mut padded = String::with_capacity(formatted.len() + padding);
+        padded.extend(std::iter::repeat_n(fill, before));
+        padded.push_str(&formatted);
+        padded.extend(std::iter::repeat_n(fill, after));
+        padded
+    }
+}
+
+fn segments(template: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(idx) = rest.find(['{', '}']) {
+        if idx > 0 {
+            segments.push(Segment::Text(&rest[..idx]));
+        }
+        let brace = &rest[idx..idx + 1];
+        rest = &rest[idx + 1..];
+        if rest.starts_with(brace) {
+            segments.push(Segment::Text(brace));
+            rest = &rest[1..];
+        } else if brace == "}" {
+            return Err("unmatched '}' in text, use '}}' for a literal brace".into());
+        } else {
+            let end = rest
+                .find('}')
+                .ok_or("unclosed '{' in text, use '{{' for a literal brace")?;
+            let placeholder = &rest[..end];
+            rest = &rest[end + 1..];
+
+            let (name, spec) = match placeholder.find(':') {
+                Some(idx) => (
+                    &placeholder[..idx],
+                    FormatSpec::parse(&placeholder[idx + 1..])?,
+                ),
+                None => (placeholder, FormatSpec::default()),
+            };
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("invalid variable name '{}' in placeholder", name));
+            }
+            segments.push(Segment::Placeholder { name, spec });
+        }
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+/// Checks that all placeholders in `template` are well-formed.
+pub(crate) fn validate(template: &str) -> Result<(), String> {
+    segments(template).map(|_| ())
+}
+
+/// Replaces the placeholders in `template` with the values returned by `lookup`.
+pub(crate) fn interpolate(
+    template: &str,
+    mut lookup: impl FnMut(&str) -> Result<Value, NovelError>,
+) -> Result<String, NovelError> {
+    // Templates are validated when parsed so this only fails for hand-built nodes
+    let segments = segments(template).map_err(NovelError::InvalidText)?;
+    let mut interpolated = String::with_capacity(template.len());
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => interpolated.push_str(text),
+            Segment::Placeholder { name, spec } => {
+                interpolated.push_str(&spec.format(&lookup(name)?));
+            }
+        }
+    }
+    Ok(interpolated)
+}
@@ -1,16 +1,96 @@
-use pest::Parser;
-use pest_derive::Parser;
-use petgraph::{graph::NodeIndex, Graph};
-use std::collections::HashMap;
+use petgraph::{graph::NodeIndex, visit::EdgeRef, Graph};
+use source::LazyScene;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
 use vec1::Vec1;
 
+mod error;
+mod expr;
+mod interpolate;
+mod parser;
+mod presentation;
+mod project;
+mod read;
+mod source;
+mod value;
+
+pub use error::{LoadError, NovelError, ParseError, SourceLocation};
+pub use expr::{BinaryOp, Comparison, Condition, Expr};
+pub use parser::{parse, parse_recovering};
+pub use presentation::{Character, Presentation};
+pub use project::Manifest;
+pub use read::ReadSet;
+#[cfg(feature = "zip")]
+pub use source::ZipSource;
+pub use source::{DirSource, MemorySource, ScriptSource};
+pub use value::Value;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum SceneNodeData {
     Text {
         speaker: Option<String>,
         content: String,
     },
-    Choice(Vec<String>),
+    Choice(Vec<ChoiceOption>),
+}
+
+/// An option of a choice that is currently available.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChoiceOption {
+    /// The 1-based index of the option in the script, this is what to pass to [`NovelState::set_choice`].
+    pub index: i32,
+    pub text: String,
+    /// Whether this option was picked any earlier time the same choice was made.
+    pub previously_picked: bool,
+}
+
+/// Something the player has seen, see [`NovelState::backlog`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BacklogEntry {
+    /// A line of text, `scene` and `line` are where it was written.
+    Line {
+        speaker: Option<String>,
+        content: String,
+        scene: String,
+        line: usize,
+    },
+    /// An option that was picked, `index` is the 1-based index of the option.
+    Choice {
+        id: String,
+        index: i32,
+        text: String,
+    },
+}
+
+/// What [`Novel::next_blocking`] stopped at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blocking {
+    /// The load nodes that were passed on the way, in order.
+    pub loads: Vec<SceneNodeLoad>,
+    /// The text or choice waiting for the player, None at the end of the novel.
+    pub node: Option<SceneNodeData>,
+}
+
+/// What [`Novel::skip_to_choice`] stopped at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    /// What is on screen and playing after all the skipped load nodes.
+    pub presentation: Presentation,
+    /// The options of the choice, None at the end of the novel.
+    pub choice: Option<Vec<ChoiceOption>>,
+}
+
+/// A choice that was made, see [`NovelState::choice_history`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChoiceRecord {
+    /// The id given with `choice id`, or `scene:` followed by the node id for choices without one.
+    pub id: String,
+    /// The 1-based indices of the options that were available.
+    pub shown: Vec<i32>,
+    /// The 1-based index of the option that was picked.
+    pub picked: i32,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,65 +112,58 @@ pub enum SceneNodeLoad {
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Comparison {
-    Equals,
-    NotEquals,
-    MoreThan,
-    LessThan,
+/// What to do when a script reads a variable that was never set.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum UndefinedVariablePolicy {
+    /// Return [`NovelError::UndefinedVariable`].
+    #[default]
+    Error,
+    /// Silently treat the variable as 0.
+    Zero,
+    /// Treat the variable as 0 and pass its name to the function, for example to log it.
+    Warn(fn(&str)),
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum CompareableData {
-    Variable(String),
-    Number(i32),
+impl UndefinedVariablePolicy {
+    fn undefined(self, name: &str) -> Result<Value, NovelError> {
+        match self {
+            UndefinedVariablePolicy::Error => Err(NovelError::UndefinedVariable(name.to_owned())),
+            UndefinedVariablePolicy::Zero => Ok(Value::Int(0)),
+            UndefinedVariablePolicy::Warn(warn) => {
+                warn(name);
+                Ok(Value::Int(0))
+            }
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct Condition {
-    first: CompareableData,
-    compare: Comparison,
-    second: CompareableData,
-}
-
-impl Condition {
-    pub fn new_reverse(mut self) -> Self {
-        self.compare = match self.compare {
-            Comparison::Equals => Comparison::NotEquals,
-            Comparison::NotEquals => Comparison::Equals,
-            // I know these are incorrect but can't fix it at the moment
-            Comparison::MoreThan => Comparison::LessThan,
-            Comparison::LessThan => Comparison::MoreThan,
-        };
-        self
-    }
+/// Where a `jump` goes, a whole scene or a label inside of one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpTarget {
+    pub scene: String,
+    pub label: Option<String>,
+}
 
-    pub fn check(&self, map: &HashMap<String, i32>) -> bool {
-        let first = match &self.first {
-            CompareableData::Number(n) => Some(n),
-            CompareableData::Variable(s) => map.get(s),
-        };
-        let second = match &self.second {
-            CompareableData::Number(n) => Some(n),
-            CompareableData::Variable(s) => map.get(s),
-        };
-        match (first, second) {
-            (Some(first), Some(second)) => match self.compare {
-                Comparison::Equals => first == second,
-                Comparison::NotEquals => first != second,
-                Comparison::MoreThan => first > second,
-                Comparison::LessThan => first < second,
-            },
-            (None, None) => panic!(
-                "No variables{:?} or {:?} are invalid",
-                self.first, self.second
-            ),
-            (None, _) => panic!("No variable {:?} is invalid", self.first),
-            (_, None) => panic!("No variable {:?} is invalid", self.second),
+impl std::fmt::Display for JumpTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "{}.{}", self.scene, label),
+            None => f.write_str(&self.scene),
         }
     }
 }
 
+/// An option of a [`SceneNodeControl::Menu`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuOption {
+    pub text: String,
+    /// The option is hidden unless this is true.
+    pub condition: Option<Condition>,
+    /// The option is hidden once it has been picked.
+    pub once: bool,
+    pub content: Vec<SceneNode>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum SceneNodeControl {
     If {
@@ -99,7 +172,181 @@ pub enum SceneNodeControl {
         else_content: Option<Vec<SceneNode>>,
         content: Vec<SceneNode>,
     },
-    Jump(String),
+    /// Shown as a [`SceneNodeData::Choice`], the next node is the start of the option
+    /// picked with [`NovelState::set_choice`].
+    Menu {
+        id: String,
+        options: Vec<MenuOption>,
+        /// Written as `[a / b]`, it can be passed without picking an option.
+        inline: bool,
+    },
+    Jump(JumpTarget),
+    /// Runs the target like a jump, then comes back here once it returns or its scene ends.
+    Call(JumpTarget),
+    /// Goes back to the last `call`, outside of a call it ends the novel.
+    Return,
+    /// A place in a scene that can be jumped to, does nothing by itself.
+    Label(String),
+    /// `variable = value`, or `variable op= value` when `op` is set.
+    Assign {
+        variable: String,
+        op: Option<BinaryOp>,
+        value: Expr,
+    },
+}
+
+impl SceneNodeControl {
+    /// The content of a branch of this node, if it has that branch.
+    fn branch_content(&self, branch: Branch) -> Option<&[SceneNode]> {
+        match (self, branch) {
+            (SceneNodeControl::If { content, .. }, Branch::First) => Some(content),
+            (SceneNodeControl::If { else_ifs, .. }, Branch::Middle(n)) => {
+                else_ifs.get(n).map(|(_, content)| content.as_slice())
+            }
+            (SceneNodeControl::If { else_content, .. }, Branch::Last) => else_content.as_deref(),
+            (SceneNodeControl::Menu { options, .. }, Branch::Option(n)) => {
+                options.get(n).map(|option| option.content.as_slice())
+            }
+            _ => None,
+        }
+    }
+
+    /// Every branch of this node together with its content.
+    fn branches(&self) -> Vec<(Branch, &[SceneNode])> {
+        match self {
+            SceneNodeControl::If {
+                content,
+                else_ifs,
+                else_content,
+                ..
+            } => std::iter::once((Branch::First, content.as_slice()))
+                .chain(
+                    else_ifs
+                        .iter()
+                        .enumerate()
+                        .map(|(n, (_, content))| (Branch::Middle(n), content.as_slice())),
+                )
+                .chain(
+                    else_content
+                        .as_deref()
+                        .map(|content| (Branch::Last, content)),
+                )
+                .collect(),
+            SceneNodeControl::Menu { options, .. } => options
+                .iter()
+                .enumerate()
+                .map(|(n, option)| (Branch::Option(n), option.content.as_slice()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The content of every branch of this node.
+    fn branches_mut(&mut self) -> Vec<&mut Vec<SceneNode>> {
+        match self {
+            SceneNodeControl::If {
+                content,
+                else_ifs,
+                else_content,
+                ..
+            } => std::iter::once(content)
+                .chain(else_ifs.iter_mut().map(|(_, content)| content))
+                .chain(else_content)
+                .collect(),
+            SceneNodeControl::Menu { options, .. } => options
+                .iter_mut()
+                .map(|option| &mut option.content)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Returns the scopes leading to `label`, the last one pointing at the label itself.
+fn find_label(content: &[SceneNode], label: &str) -> Option<Vec<Scope>> {
+    for (index, node) in content.iter().enumerate() {
+        let node = match &node.kind {
+            SceneNodeKind::Control(node) => node,
+            SceneNodeKind::User(_) => continue,
+        };
+        if let SceneNodeControl::Label(name) = node {
+            if name == label {
+                return Some(vec![Scope {
+                    index: Some(index),
+                    ..Scope::default()
+                }]);
+            }
+        }
+        for (branch, content) in node.branches() {
+            if let Some(mut scopes) = find_label(content, label) {
+                scopes.insert(
+                    0,
+                    Scope {
+                        index: Some(index),
+                        branch: Some(branch),
+                        ..Scope::default()
+                    },
+                );
+                return Some(scopes);
+            }
+        }
+    }
+    None
+}
+
+/// The content left to run after the node `scopes` points at, innermost block first.
+fn remaining_content<'a>(mut content: &'a [SceneNode], scopes: &[Scope]) -> Vec<&'a [SceneNode]> {
+    let mut remaining = Vec::new();
+    for scope in scopes {
+        let index = scope.index.unwrap_or(0);
+        remaining.push(content.get(index + 1..).unwrap_or_default());
+        content = match (content.get(index).map(|node| &node.kind), scope.branch) {
+            (Some(SceneNodeKind::Control(node)), Some(branch)) => {
+                node.branch_content(branch).unwrap_or_default()
+            }
+            _ => break,
+        };
+    }
+    remaining.reverse();
+    remaining
+}
+
+/// Stores the id of the node each scope points at, so the state can be relocated later.
+fn anchor(mut content: &[SceneNode], scopes: &mut [Scope]) {
+    for scope in scopes {
+        let node = scope.index.and_then(|index| content.get(index));
+        scope.node = node.map(|node| node.id.clone());
+        content = match (node.map(|node| &node.kind), scope.branch) {
+            (Some(SceneNodeKind::Control(node)), Some(branch)) => {
+                node.branch_content(branch).unwrap_or_default()
+            }
+            _ => &[],
+        };
+    }
+}
+
+/// Points every scope back at the node with its id, see [`Novel::relocate`].
+/// Returns the id that couldn't be found if there is one.
+fn relocate_scopes(mut content: &[SceneNode], scopes: &mut [Scope]) -> Result<(), String> {
+    for scope in scopes {
+        let id = match &scope.node {
+            Some(id) => id,
+            // Older saves and states past the end of a scene have nothing to go by
+            None => continue,
+        };
+        let index = content
+            .iter()
+            .position(|node| node.id == *id)
+            .ok_or_else(|| id.clone())?;
+        scope.index = Some(index);
+        content = match (&content[index].kind, scope.branch) {
+            (SceneNodeKind::Control(node), Some(branch)) => {
+                node.branch_content(branch).ok_or_else(|| id.clone())?
+            }
+            _ => &[],
+        };
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -109,24 +356,56 @@ pub enum SceneNodeUser {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum SceneNode {
+pub enum SceneNodeKind {
     User(SceneNodeUser),
     Control(SceneNodeControl),
 }
 
+/// Where a node was parsed from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    pub scene: String,
+    /// Byte offset of the start of the statement.
+    pub start: usize,
+    /// Byte offset of the end of the statement.
+    pub end: usize,
+    /// 1-based line number of the start of the statement.
+    pub line: usize,
+    /// 1-based column number of the start of the statement.
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneNode {
+    pub kind: SceneNodeKind,
+    pub span: Span,
+    /// Either given with a `#tag` before the statement or made from its content,
+    /// saves use it to find their place again after the script was edited.
+    pub id: String,
+}
+
 #[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
 enum Branch {
     First,
     Middle(usize),
     Last,
+    /// The 0-based option of a menu.
+    Option(usize),
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 struct Scope {
     /// This is the index of the node that was next'd. it's None when nothing has been loaded.
     index: Option<usize>,
+    /// The option picked with [`NovelState::set_choice`] for the menu at `index`.
     choice: i32,
+    /// The option last picked from a menu in this scope, what `if choice = n` checks.
+    #[serde(default)]
+    picked: i32,
     branch: Option<Branch>,
+    /// The id of the node at `index`, see [`Novel::relocate`].
+    #[serde(default)]
+    node: Option<String>,
 }
 
 impl Scope {
@@ -139,23 +418,216 @@ impl Scope {
     }
 }
 
+/// Identifies a `once` option that has been picked.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+struct PickedOption {
+    scene: String,
+    node: String,
+    option: usize,
+}
+
+impl PickedOption {
+    fn new(menu: &SceneNode, option: usize) -> Self {
+        PickedOption {
+            scene: menu.span.scene.clone(),
+            node: menu.id.clone(),
+            option,
+        }
+    }
+}
+
+/// Where to continue after a `call` returns.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Frame {
+    scene: String,
+    scopes: Vec1<Scope>,
+}
+
+/// What [`Novel::rollback`] puts a state back to. The choice history and backlog only grow,
+/// so only how long they were is kept.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    position: Frame,
+    calls: Vec<Frame>,
+    variables: HashMap<String, Value>,
+    picked_once: HashSet<PickedOption>,
+    choice_history: usize,
+    backlog_added: usize,
+    presentation: Presentation,
+}
+
+impl Snapshot {
+    /// Where the state was and the calls it was in.
+    fn frames(&self) -> impl Iterator<Item = &Frame> {
+        std::iter::once(&self.position).chain(&self.calls)
+    }
+}
+
+/// The format [`NovelState`] is saved in, raised whenever that changes.
+/// Saves from before it was stored have format 0.
+pub const STATE_FORMAT: u32 = 1;
+
+/// Upgrades a state from one version of the scripts to the next, see [`Novel::add_migration`].
+pub type Migration = fn(&mut NovelState) -> Result<(), NovelError>;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct NovelState {
+    #[serde(default)]
+    format: u32,
+    /// The version of the scripts the state was made with, see [`Novel::set_version`].
+    #[serde(default)]
+    version: u32,
     scene: String,
-    variables: HashMap<String, i32>,
+    variables: HashMap<String, Value>,
     scopes: Vec1<Scope>,
+    #[serde(default)]
+    calls: Vec<Frame>,
+    #[serde(default)]
+    picked_once: HashSet<PickedOption>,
+    #[serde(default)]
+    choice_history: Vec<ChoiceRecord>,
+    #[serde(default)]
+    backlog: VecDeque<BacklogEntry>,
+    #[serde(default)]
+    presentation: Presentation,
+    /// How many entries were ever added to the backlog, so a rollback knows how many to take out.
+    #[serde(default)]
+    backlog_added: usize,
+    /// The state at each of the last lines and menus, newest last, see [`Novel::rollback`].
+    #[serde(default)]
+    rollback: VecDeque<Snapshot>,
 }
 
 impl NovelState {
-    pub fn set_variable(&mut self, name: String, data: i32) {
+    pub fn set_variable(&mut self, name: String, data: impl Into<Value>) {
         if name.as_str() == "choice" {
             panic!("Don't use set choice with set_variable, use set_choice instead");
         }
-        self.variables.insert(name, data);
+        self.variables.insert(name, data.into());
+    }
+
+    /// A variable as scripts see it, `choice` is the option last picked in the current scope.
+    pub(crate) fn script_variable(&self, name: &str) -> Option<Value> {
+        match name {
+            "choice" => Some(Value::Int(self.scopes.last().picked)),
+            _ => self.variables.get(name).cloned(),
+        }
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    pub fn remove_variable(&mut self, name: &str) -> Option<Value> {
+        self.variables.remove(name)
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i32> {
+        self.get_variable(name).and_then(Value::as_int)
+    }
+
+    /// Int variables are converted to floats.
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        self.get_variable(name).and_then(Value::as_float)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get_variable(name).and_then(Value::as_bool)
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get_variable(name).and_then(Value::as_str)
+    }
+
+    pub fn scene(&self) -> &str {
+        &self.scene
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Moves the state and the calls it's in from scene `from` to `to`, for migrations of renamed scenes.
+    /// The nodes are found again by their ids when the state is loaded, see [`Novel::relocate`].
+    pub fn rename_scene(&mut self, from: &str, to: &str) {
+        let scenes = std::iter::once(&mut self.scene)
+            .chain(self.calls.iter_mut().map(|frame| &mut frame.scene));
+        for scene in scenes {
+            if scene == from {
+                *scene = to.to_owned();
+            }
+        }
+    }
+
+    /// The background, characters and sounds set by the load nodes passed so far.
+    pub fn presentation(&self) -> &Presentation {
+        &self.presentation
     }
 
+    /// The lines and choices recorded so far, oldest first, see [`Novel::set_backlog_size`].
+    pub fn backlog(&self) -> &VecDeque<BacklogEntry> {
+        &self.backlog
+    }
+
+    /// Every choice made so far, oldest first.
+    pub fn choice_history(&self) -> &[ChoiceRecord] {
+        &self.choice_history
+    }
+
+    /// The option picked the last time the choice `id` was made.
+    pub fn last_choice(&self, id: &str) -> Option<i32> {
+        self.choice_history
+            .iter()
+            .rev()
+            .find(|record| record.id == id)
+            .map(|record| record.picked)
+    }
+
+    /// Whether `option` was picked any time the choice `id` was made.
+    pub fn has_picked(&self, id: &str, option: i32) -> bool {
+        self.choice_history
+            .iter()
+            .any(|record| record.id == id && record.picked == option)
+    }
+
+    /// How many steps [`Novel::rollback`] can go back.
+    pub fn rollback_len(&self) -> usize {
+        self.rollback.len()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            position: Frame {
+                scene: self.scene.clone(),
+                scopes: self.scopes.clone(),
+            },
+            calls: self.calls.clone(),
+            variables: self.variables.clone(),
+            picked_once: self.picked_once.clone(),
+            choice_history: self.choice_history.len(),
+            backlog_added: self.backlog_added,
+            presentation: self.presentation.clone(),
+        }
+    }
+
+    /// Puts the state back to `snapshot`, the rollback history is left as it is.
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.scene = snapshot.position.scene;
+        self.scopes = snapshot.position.scopes;
+        self.calls = snapshot.calls;
+        self.variables = snapshot.variables;
+        self.picked_once = snapshot.picked_once;
+        self.choice_history.truncate(snapshot.choice_history);
+        let added = self.backlog_added - snapshot.backlog_added;
+        self.backlog
+            .truncate(self.backlog.len().saturating_sub(added));
+        self.backlog_added = snapshot.backlog_added;
+        self.presentation = snapshot.presentation;
+    }
+
+    /// Picks the 1-based option of the menu the state is at, [`Novel::next`] then enters it.
+    /// Every menu needs a pick of its own, except `[a / b]` choices which can be passed without one.
     pub fn set_choice(&mut self, choice: i32) {
-        println!("set choice to {}", choice);
         self.scopes.last_mut().choice = choice;
     }
 }
@@ -163,29 +635,84 @@ impl NovelState {
 #[derive(Debug)]
 pub enum GraphNode<'a> {
     Root,
-    Node { node: &'a SceneNodeUser },
+    Node {
+        node: &'a SceneNodeUser,
+    },
     Branch(Condition),
+    /// The content of the called scene is its children.
+    Call(&'a JumpTarget),
+    /// The options of the menu are its children.
+    Menu,
+    /// The content of the option is its children, `choice` is what to pass to [`NovelState::set_choice`].
+    Option {
+        choice: i32,
+        option: &'a MenuOption,
+    },
+    /// Has a [`GraphEdge::Return`] edge to the [`GraphNode::Call`] it returns to.
+    Return,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphEdge {
+    /// From a node to the nodes in it, in order.
+    Child,
+    /// From a [`GraphNode::Return`] back to its [`GraphNode::Call`].
+    Return,
 }
 
 pub trait RevNeighbors {
+    /// The children of `a` in order, [`GraphEdge::Return`] edges are skipped.
     fn rev_neighbors(
         &self,
         a: NodeIndex<u32>,
     ) -> std::iter::Rev<<Vec<NodeIndex> as IntoIterator>::IntoIter>;
 }
 
-impl<'a> RevNeighbors for Graph<GraphNode<'a>, ()> {
+impl<'a> RevNeighbors for Graph<GraphNode<'a>, GraphEdge> {
     fn rev_neighbors(
         &self,
         a: NodeIndex<u32>,
     ) -> std::iter::Rev<<Vec<NodeIndex> as IntoIterator>::IntoIter> {
-        self.neighbors(a).collect::<Vec<_>>().into_iter().rev()
+        self.edges(a)
+            .filter(|edge| *edge.weight() == GraphEdge::Child)
+            .map(|edge| edge.target())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// What [`Novel::parse_into_graph`] is currently inside of.
+#[derive(Default)]
+struct GraphWalk<'a> {
+    /// The jumps and calls being followed, so loops are only walked once.
+    jumps: Vec<&'a JumpTarget>,
+    /// The call nodes that a return goes back to.
+    calls: Vec<NodeIndex>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Novel {
-    scenes: HashMap<String, Vec<SceneNode>>,
+    scenes: HashMap<String, LazyScene>,
+    undefined_variables: UndefinedVariablePolicy,
+    rollback_depth: usize,
+    backlog_size: usize,
+    version: u32,
+    /// The migration from each version to the next.
+    migrations: HashMap<u32, Migration>,
+}
+
+impl Default for Novel {
+    fn default() -> Self {
+        Novel {
+            scenes: HashMap::new(),
+            undefined_variables: UndefinedVariablePolicy::default(),
+            rollback_depth: 100,
+            backlog_size: 0,
+            version: 0,
+            migrations: HashMap::new(),
+        }
+    }
 }
 
 impl Novel {
@@ -193,348 +720,773 @@ impl Novel {
         Novel::default()
     }
 
+    pub fn set_undefined_variable_policy(&mut self, policy: UndefinedVariablePolicy) {
+        self.undefined_variables = policy;
+    }
+
+    /// How many lines and menus back [`Novel::rollback`] can go, 0 turns rollback off. Defaults to 100.
+    pub fn set_rollback_depth(&mut self, depth: usize) {
+        self.rollback_depth = depth;
+    }
+
+    /// How many lines and choices to keep in the backlog of a state, 0 turns it off. Defaults to 0.
+    pub fn set_backlog_size(&mut self, size: usize) {
+        self.backlog_size = size;
+    }
+
+    /// The version of the scripts that new states are stamped with, raise it when a release
+    /// changes them in a way that old saves need a migration for. Defaults to 0.
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    /// Registers the migration that upgrades states from version `from` to `from + 1`.
+    /// Versions without a migration are upgraded as they are. A migration can change the variables
+    /// and move the state off scenes that were renamed with [`NovelState::rename_scene`].
+    pub fn add_migration(&mut self, from: u32, migration: Migration) {
+        self.migrations.insert(from, migration);
+    }
+
     pub fn new_state(&self, starting_scene: &str) -> NovelState {
         NovelState {
+            format: STATE_FORMAT,
+            version: self.version,
             scene: starting_scene.to_owned(),
             variables: HashMap::new(),
             scopes: Vec1::new(Scope::default()),
+            calls: Vec::new(),
+            picked_once: HashSet::new(),
+            choice_history: Vec::new(),
+            backlog: VecDeque::new(),
+            presentation: Presentation::default(),
+            backlog_added: 0,
+            rollback: VecDeque::new(),
         }
     }
 
-    pub fn add_scene(&mut self, name: String, data: &str) {
-        self.add_nodes(name, parse(data));
+    /// Parses and adds a scene, a scene with the same name can't have been added before.
+    pub fn add_scene(&mut self, name: String, data: &str) -> Result<(), LoadError> {
+        if self.scenes.contains_key(&name) {
+            return Err(LoadError::DuplicateScene(name));
+        }
+        let nodes = parse(&name, data)?;
+        self.scenes.insert(name, LazyScene::parsed(nodes));
+        Ok(())
     }
 
-    pub fn add_nodes(&mut self, name: String, data: Vec<SceneNode>) {
-        self.scenes.insert(name, data);
+    /// Adds nodes that were built by hand as a scene, replacing any scene with the same name.
+    /// Their jumps to labels are resolved and nodes without an id are given one, like parsing does.
+    pub fn add_nodes(&mut self, name: String, mut data: Vec<SceneNode>) -> Result<(), LoadError> {
+        parser::prepare_nodes(&name, &mut data)?;
+        self.scenes.insert(name, LazyScene::parsed(data));
+        Ok(())
+    }
+
+    /// Adds every scene of `source`, they're read and parsed the first time they're used.
+    pub fn add_source(&mut self, source: impl ScriptSource + 'static) -> Result<(), LoadError> {
+        let names = source.scenes()?;
+        for name in &names {
+            project::check_scene_name(name)?;
+        }
+        if let Some(name) = names.iter().find(|name| self.scenes.contains_key(*name)) {
+            return Err(LoadError::DuplicateScene(name.clone()));
+        }
+        let source: Arc<dyn ScriptSource> = Arc::new(source);
+        for name in names {
+            self.scenes
+                .insert(name, LazyScene::from_source(source.clone()));
+        }
+        Ok(())
+    }
+
+    /// The nodes of a scene, parsing it first if it comes from a [`ScriptSource`].
+    fn scene(&self, name: &str) -> Result<&[SceneNode], NovelError> {
+        self.scenes
+            .get(name)
+            .ok_or_else(|| NovelError::SceneNotFound(name.to_owned()))?
+            .nodes(name)
+    }
+
+    /// The content a jump or call to `target` runs, see [`remaining_content`].
+    fn target_content<'a>(
+        &'a self,
+        target: &JumpTarget,
+    ) -> Result<Vec<&'a [SceneNode]>, NovelError> {
+        let scene = self.scene(&target.scene)?;
+        Ok(match &target.label {
+            Some(label) => {
+                let scopes = find_label(scene, label).ok_or_else(|| NovelError::LabelNotFound {
+                    scene: target.scene.clone(),
+                    label: label.clone(),
+                })?;
+                remaining_content(scene, &scopes)
+            }
+            None => vec![scene],
+        })
     }
 
     fn parse_into_graph<'a>(
         &'a self,
-        graph: &mut Graph<GraphNode<'a>, ()>,
+        graph: &mut Graph<GraphNode<'a>, GraphEdge>,
         parent: NodeIndex,
         content: &'a [SceneNode],
-    ) {
+        walk: &mut GraphWalk<'a>,
+    ) -> Result<(), NovelError> {
         for node in content {
-            match node {
-                SceneNode::User(node) => {
+            match &node.kind {
+                SceneNodeKind::User(node) => {
                     let graph_node = graph.add_node(GraphNode::Node { node });
-                    graph.update_edge(parent, graph_node, ());
+                    graph.update_edge(parent, graph_node, GraphEdge::Child);
                 }
-                SceneNode::Control(node) => match node {
+                SceneNodeKind::Control(node) => match node {
                     SceneNodeControl::If {
                         cond,
                         else_ifs,
                         else_content,
                         content,
                     } => {
+                        // Each branch is only taken if none of the ones before it were
+                        let mut previous_failed: Option<Condition> = None;
+                        for (cond, content) in std::iter::once((cond, content))
+                            .chain(else_ifs.iter().map(|(c, b)| (c, b)))
                         {
-                            let graph_node = graph.add_node(GraphNode::Branch(cond.clone()));
-                            graph.update_edge(parent, graph_node, ());
-                            self.parse_into_graph(graph, graph_node, content);
+                            let guard = match &previous_failed {
+                                Some(failed) => failed.clone().and(cond.clone()),
+                                None => cond.clone(),
+                            };
+                            let graph_node = graph.add_node(GraphNode::Branch(guard));
+                            graph.update_edge(parent, graph_node, GraphEdge::Child);
+                            self.parse_into_graph(graph, graph_node, content, walk)?;
+
+                            let reverse = cond.clone().new_reverse();
+                            previous_failed = Some(match previous_failed {
+                                Some(failed) => failed.and(reverse),
+                                None => reverse,
+                            });
                         }
-                        for (cond, content) in else_ifs {
-                            let graph_node = graph.add_node(GraphNode::Branch(cond.clone()));
-                            graph.update_edge(parent, graph_node, ());
-                            self.parse_into_graph(graph, graph_node, content);
+                        if let (Some(content), Some(guard)) = (else_content, previous_failed) {
+                            let graph_node = graph.add_node(GraphNode::Branch(guard));
+                            graph.update_edge(parent, graph_node, GraphEdge::Child);
+                            self.parse_into_graph(graph, graph_node, content, walk)?;
                         }
-                        if let Some(content) = else_content {
-                            let graph_node =
-                                graph.add_node(GraphNode::Branch(cond.clone().new_reverse()));
-                            graph.update_edge(parent, graph_node, ());
-                            self.parse_into_graph(graph, graph_node, content);
+                    }
+                    SceneNodeControl::Menu { options, .. } => {
+                        let menu = graph.add_node(GraphNode::Menu);
+                        graph.update_edge(parent, menu, GraphEdge::Child);
+                        for (choice, option) in (1..).zip(options) {
+                            let graph_node = graph.add_node(GraphNode::Option { choice, option });
+                            graph.update_edge(menu, graph_node, GraphEdge::Child);
+                            self.parse_into_graph(graph, graph_node, &option.content, walk)?;
                         }
                     }
+                    SceneNodeControl::Assign { .. } | SceneNodeControl::Label(_) => {}
                     SceneNodeControl::Jump(target) => {
-                        let scene = self
-                            .scenes
-                            .get(target)
-                            .unwrap_or_else(|| panic!("Couldn't find scene '{}'", target));
-                        self.parse_into_graph(graph, parent, scene);
+                        if walk.jumps.contains(&target) {
+                            continue;
+                        }
+                        walk.jumps.push(target);
+                        for content in self.target_content(target)? {
+                            self.parse_into_graph(graph, parent, content, walk)?;
+                        }
+                        walk.jumps.pop();
+                    }
+                    SceneNodeControl::Call(target) => {
+                        let call = graph.add_node(GraphNode::Call(target));
+                        graph.update_edge(parent, call, GraphEdge::Child);
+                        if walk.jumps.contains(&target) {
+                            continue;
+                        }
+                        walk.jumps.push(target);
+                        walk.calls.push(call);
+                        for content in self.target_content(target)? {
+                            self.parse_into_graph(graph, call, content, walk)?;
+                        }
+                        // Reaching the end of the called content returns as well
+                        let graph_node = graph.add_node(GraphNode::Return);
+                        graph.update_edge(call, graph_node, GraphEdge::Child);
+                        graph.update_edge(graph_node, call, GraphEdge::Return);
+                        walk.calls.pop();
+                        walk.jumps.pop();
+                    }
+                    SceneNodeControl::Return => {
+                        let graph_node = graph.add_node(GraphNode::Return);
+                        graph.update_edge(parent, graph_node, GraphEdge::Child);
+                        if let Some(&call) = walk.calls.last() {
+                            graph.update_edge(graph_node, call, GraphEdge::Return);
+                        }
                     }
                 },
             }
         }
+        Ok(())
     }
 
     pub fn extract_graph<'a>(
         &'a self,
         starting_scene: &str,
-    ) -> (Graph<GraphNode<'a>, ()>, NodeIndex) {
-        let mut graph = Graph::<GraphNode, ()>::new();
+    ) -> Result<(Graph<GraphNode<'a>, GraphEdge>, NodeIndex), NovelError> {
+        let mut graph = Graph::<GraphNode, GraphEdge>::new();
         let root = graph.add_node(GraphNode::Root);
-        let scene = self
-            .scenes
-            .get(starting_scene)
-            .unwrap_or_else(|| panic!("Couldn't find scene '{}'", starting_scene));
-        self.parse_into_graph(&mut graph, root, scene);
-        (graph, root)
+        let scene = self.scene(starting_scene)?;
+        self.parse_into_graph(&mut graph, root, scene, &mut GraphWalk::default())?;
+        Ok((graph, root))
     }
 
-    pub fn next<'a>(&'a self, state: &mut NovelState) -> Option<&'a SceneNodeUser> {
-        state.scopes.last_mut().inc();
-        self.current(state)
-    }
-
-    pub fn current<'a>(&'a self, state: &mut NovelState) -> Option<&'a SceneNodeUser> {
-        let active_node = {
-            let active_scene = &self
-                .scenes
-                .get(&state.scene)
-                .unwrap_or_else(|| panic!("Couldn't find scene '{}'", state.scene));
-
-            let mut prev_scope = &state.scopes[0];
-            let mut active_node =
-                active_scene.get(prev_scope.index.expect("Expected a scope index"));
-            for scope in &state.scopes[1..] {
-                if let Some(SceneNode::Control(SceneNodeControl::If {
-                    cond: _,
-                    content,
-                    else_ifs,
-                    else_content,
-                })) = active_node
-                {
-                    if let Some(branch) = prev_scope.branch {
-                        active_node = match branch {
-                            Branch::First => {
-                                content.get(scope.index.expect("Expected a scope index"))
-                            }
-                            Branch::Middle(n) => else_ifs
-                                .get(n)
-                                .map(|o| o.1.get(scope.index.expect("Expected a scope index")))
-                                .flatten(),
-                            Branch::Last => else_content
-                                .as_ref()
-                                .map(|c| c.get(scope.index.expect("Expected a scope index")))
-                                .flatten(),
-                        }
+    /// Advances the state and returns the next node, with `{variable}` placeholders in its text filled in.
+    /// When the state is at a menu this enters the option picked with [`NovelState::set_choice`].
+    /// On an error the state is left as it was, so the call can be retried once the cause is fixed.
+    pub fn next(&self, state: &mut NovelState) -> Result<Option<SceneNodeUser>, NovelError> {
+        let visible = self.at_visible_node(state);
+        let snapshot = state.snapshot();
+        match self.step(state) {
+            Ok(node) => {
+                if self.rollback_depth > 0 && visible {
+                    state.rollback.push_back(snapshot);
+                    while state.rollback.len() > self.rollback_depth {
+                        state.rollback.pop_front();
                     }
                 }
-                prev_scope = scope;
+                while state.backlog.len() > self.backlog_size {
+                    state.backlog.pop_front();
+                }
+                Ok(node)
             }
-            active_node
-        };
-        let node = match active_node {
-            Some(node) => match node {
-                SceneNode::User(node) => Some(node),
-                SceneNode::Control(node) => match node {
-                    SceneNodeControl::If {
-                        cond,
-                        content: _,
-                        else_ifs,
-                        else_content,
-                    } => {
-                        // Hacky fix for scoped choices
-                        state
-                            .variables
-                            .insert("choice".into(), state.scopes.last().choice);
-                        if cond.check(&state.variables) {
-                            state.scopes.last_mut().branch = Some(Branch::First);
-                            state.scopes.push(Scope::default())
-                        } else if let Some((n, _)) = else_ifs
-                            .iter()
-                            .enumerate()
-                            .find(|(_, (else_if_cond, _))| else_if_cond.check(&state.variables))
-                        {
-                            state.scopes.last_mut().branch = Some(Branch::Middle(n));
-                            state.scopes.push(Scope::default())
-                        } else if else_content.is_some() {
-                            state.scopes.last_mut().branch = Some(Branch::Last);
-                            state.scopes.push(Scope::default())
-                        }
-
-                        return self.next(state);
-                    }
-                    SceneNodeControl::Jump(target) => {
-                        state.scopes = Vec1::new(Scope::default());
-                        state.scene = target.clone();
+            Err(err) => {
+                state.restore(snapshot);
+                Err(err)
+            }
+        }
+    }
 
-                        return self.next(state);
-                    }
-                },
+    fn step(&self, state: &mut NovelState) -> Result<Option<SceneNodeUser>, NovelError> {
+        if let Ok(Some(
+            menu @ SceneNode {
+                kind:
+                    SceneNodeKind::Control(SceneNodeControl::Menu {
+                        id,
+                        options,
+                        inline,
+                    }),
+                ..
             },
-            None => {
-                if let Ok(..) = state.scopes.try_pop() {
-                    state.scopes.last_mut().branch = None;
-                    return self.next(state);
-                } else {
-                    None
+        )) = self.active_node(state)
+        {
+            let choice = state.scopes.last().choice;
+            // Like choices before menus existed, a bracket choice can be passed without a pick
+            if choice == 0 && *inline {
+                state.scopes.last_mut().picked = 0;
+            } else {
+                self.pick(state, menu, id, options, choice)?;
+            }
+        }
+        // A finished state stays at the end of its scene instead of moving past it
+        let finished = state.calls.is_empty()
+            && state.scopes.len() == 1
+            && matches!(self.active_node(state), Ok(None));
+        if !finished {
+            state.scopes.last_mut().inc();
+        }
+
+        let node = self.current(state)?;
+        match &node {
+            Some(SceneNodeUser::Data(SceneNodeData::Text { speaker, content }))
+                if self.backlog_size > 0 =>
+            {
+                let span = self.current_span(state)?.unwrap();
+                let entry = BacklogEntry::Line {
+                    speaker: speaker.clone(),
+                    content: content.clone(),
+                    scene: span.scene.clone(),
+                    line: span.line,
+                };
+                self.push_backlog(state, entry);
+            }
+            Some(SceneNodeUser::Load(load)) => state.presentation.apply(load),
+            _ => {}
+        }
+        Ok(node)
+    }
+
+    /// Enters the option `choice` of the menu the state is at and records the pick.
+    fn pick(
+        &self,
+        state: &mut NovelState,
+        menu: &SceneNode,
+        id: &str,
+        options: &[MenuOption],
+        choice: i32,
+    ) -> Result<(), NovelError> {
+        if choice < 1 || choice as usize > options.len() {
+            return Err(NovelError::InvalidChoice {
+                choice,
+                options: options.len(),
+            });
+        }
+        let visible = self.visible_options(state, menu, options)?;
+        let (_, option) = visible
+            .iter()
+            .find(|(index, _)| *index == choice)
+            .ok_or(NovelError::UnavailableChoice(choice))?;
+        let option_index = choice as usize - 1;
+        if self.backlog_size > 0 {
+            let entry = BacklogEntry::Choice {
+                id: id.to_owned(),
+                index: choice,
+                text: self.fill_text(&option.text, state)?,
+            };
+            self.push_backlog(state, entry);
+        }
+        state.choice_history.push(ChoiceRecord {
+            id: id.to_owned(),
+            shown: visible.iter().map(|(index, _)| *index).collect(),
+            picked: choice,
+        });
+        if option.once {
+            state
+                .picked_once
+                .insert(PickedOption::new(menu, option_index));
+        }
+        let scope = state.scopes.last_mut();
+        scope.picked = choice;
+        // Cleared so that the next menu in this scope needs a choice of its own
+        scope.choice = 0;
+        scope.branch = Some(Branch::Option(option_index));
+        state.scopes.push(Scope::default());
+        Ok(())
+    }
+
+    /// Advances past any load nodes to the next text or choice, returning the loads together with it.
+    pub fn next_blocking(&self, state: &mut NovelState) -> Result<Blocking, NovelError> {
+        let mut loads = Vec::new();
+        loop {
+            match self.next(state)? {
+                Some(SceneNodeUser::Load(load)) => loads.push(load),
+                Some(SceneNodeUser::Data(node)) => {
+                    return Ok(Blocking {
+                        loads,
+                        node: Some(node),
+                    })
                 }
+                None => return Ok(Blocking { loads, node: None }),
             }
-        };
-        node
+        }
     }
-}
 
-#[derive(Parser)]
-#[grammar = "novelscript.pest"]
-struct NovelscriptParser;
+    /// Advances past all text and load nodes to the next choice.
+    /// Only the net effect of the skipped load nodes is returned, as [`NovelState::presentation`].
+    pub fn skip_to_choice(&self, state: &mut NovelState) -> Result<Skipped, NovelError> {
+        let choice = loop {
+            match self.next(state)? {
+                Some(SceneNodeUser::Data(SceneNodeData::Choice(options))) => break Some(options),
+                Some(_) => {}
+                None => break None,
+            }
+        };
+        Ok(Skipped {
+            presentation: state.presentation.clone(),
+            choice,
+        })
+    }
 
-fn parse_if(mut pair_it: pest::iterators::Pairs<Rule>) -> (Condition, Vec<SceneNode>) {
-    let condition = {
-        let mut cond_it = pair_it.next().unwrap().into_inner();
-        let first = cond_it.next().unwrap().as_str().trim();
-        let compare = match cond_it.next().unwrap().as_str() {
-            "=" => Comparison::Equals,
-            "!=" => Comparison::NotEquals,
-            ">" => Comparison::MoreThan,
-            "<" => Comparison::LessThan,
-            c => panic!("{}", c),
+    /// Like [`Novel::next`], but marks text as read in `read_set` and also returns whether it
+    /// had been read before. Nodes other than text are never counted as read.
+    pub fn next_with_read_set(
+        &self,
+        state: &mut NovelState,
+        read_set: &mut ReadSet,
+    ) -> Result<Option<(SceneNodeUser, bool)>, NovelError> {
+        let node = match self.next(state)? {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+        // The unfilled text is used so that a line stays read whatever the variables are
+        let read = match self.active_node(state)? {
+            Some(SceneNode {
+                kind:
+                    SceneNodeKind::User(SceneNodeUser::Data(SceneNodeData::Text { speaker, content })),
+                span,
+                ..
+            }) => read_set.insert(&span.scene, speaker.as_deref(), content),
+            _ => false,
         };
-        let second = cond_it.next().unwrap().as_str().trim();
+        Ok(Some((node, read)))
+    }
 
-        Condition {
-            first: match first.parse() {
-                Ok(n) => CompareableData::Number(n),
-                Err(_) => CompareableData::Variable(first.to_owned()),
-            },
-            compare,
-            second: match second.parse() {
-                Ok(n) => CompareableData::Number(n),
-                Err(_) => CompareableData::Variable(second.to_owned()),
-            },
+    /// Whether the state is at a line or a menu, the nodes that [`Novel::rollback`] steps back over.
+    fn at_visible_node(&self, state: &NovelState) -> bool {
+        matches!(
+            self.active_node(state),
+            Ok(Some(SceneNode {
+                kind: SceneNodeKind::User(SceneNodeUser::Data(_))
+                    | SceneNodeKind::Control(SceneNodeControl::Menu { .. }),
+                ..
+            }))
+        )
+    }
+
+    /// The backlog is trimmed to its size once [`Novel::next`] succeeds.
+    fn push_backlog(&self, state: &mut NovelState, entry: BacklogEntry) {
+        state.backlog.push_back(entry);
+        state.backlog_added += 1;
+    }
+
+    /// Puts the state back to how it was `steps` lines or menus ago and returns that node again.
+    /// Everything is restored, so a choice can be picked again.
+    pub fn rollback(
+        &self,
+        state: &mut NovelState,
+        steps: usize,
+    ) -> Result<Option<SceneNodeUser>, NovelError> {
+        if steps == 0 || steps > state.rollback.len() {
+            return Err(NovelError::RollbackTooFar {
+                steps,
+                available: state.rollback.len(),
+            });
         }
-    };
-    let statement_list = pair_it
-        .next()
-        .unwrap()
-        .into_inner()
-        .map(|statement| parse_statement(statement.into_inner().next().unwrap()))
-        .collect::<Vec<_>>();
-
-    (condition, statement_list)
-}
-
-fn parse_statement(pair: pest::iterators::Pair<'_, Rule>) -> SceneNode {
-    match pair.as_rule() {
-        Rule::choice_statement => {
-            let choices = pair
-                .into_inner()
-                .map(|choice| choice.as_str().trim().to_owned())
-                .collect::<Vec<_>>();
-            SceneNode::User(SceneNodeUser::Data(SceneNodeData::Choice(choices)))
-        }
-        Rule::if_statement => {
-            let mut pairs_it = pair.into_inner();
-            let (if_cond, if_content) = { parse_if(pairs_it.next().unwrap().into_inner()) };
-            let mut else_content = None;
-            let mut else_ifs = Vec::new();
-            for case in pairs_it {
-                match case.as_rule() {
-                    Rule::else_if_case => {
-                        let pair_it = case.into_inner().next().unwrap().into_inner();
-                        else_ifs.push(parse_if(pair_it));
-                    }
-                    Rule::else_case => {
-                        let statement_it = case.into_inner().next().unwrap().into_inner();
-                        else_content = Some(
-                            statement_it
-                                .map(|statement| {
-                                    parse_statement(statement.into_inner().next().unwrap())
-                                })
-                                .collect::<Vec<_>>(),
-                        );
-                    }
-                    _ => unreachable!(),
+        let start = state.rollback.len() - steps;
+        let snapshot = state.rollback.drain(start..).next().unwrap();
+        state.restore(snapshot);
+        self.current(state)
+    }
+
+    /// Prepares a deserialized state for use with the current scripts.
+    /// It's migrated to the current version, relocated with [`Novel::relocate`]
+    /// and then checked with [`Novel::validate_state`].
+    pub fn load_state(&self, mut state: NovelState) -> Result<NovelState, NovelError> {
+        if state.format > STATE_FORMAT {
+            return Err(NovelError::UnsupportedFormat {
+                found: state.format,
+                supported: STATE_FORMAT,
+            });
+        }
+        if state.version > self.version {
+            return Err(NovelError::NewerVersion {
+                found: state.version,
+                current: self.version,
+            });
+        }
+        // Migrations only upgrade the state itself, not the rollback history made with older scripts
+        if state.version < self.version {
+            state.rollback.clear();
+        }
+        self.migrate(&mut state)?;
+        self.relocate(&mut state)?;
+        self.validate_state(&state)?;
+        if !state.rollback.iter().all(|snapshot| {
+            self.validate_positions(snapshot.frames().map(|frame| (&frame.scene, &frame.scopes)))
+                .is_ok()
+        }) {
+            state.rollback.clear();
+        }
+        Ok(state)
+    }
+
+    fn migrate(&self, state: &mut NovelState) -> Result<(), NovelError> {
+        while state.version < self.version {
+            if let Some(migration) = self.migrations.get(&state.version) {
+                migration(state)?;
+            }
+            state.version += 1;
+        }
+        state.format = STATE_FORMAT;
+        Ok(())
+    }
+
+    /// Checks that the scene, scopes and branches of the state and its calls exist in the loaded scripts.
+    pub fn validate_state(&self, state: &NovelState) -> Result<(), NovelError> {
+        self.validate_positions(
+            std::iter::once((&state.scene, &state.scopes)).chain(
+                state
+                    .calls
+                    .iter()
+                    .map(|frame| (&frame.scene, &frame.scopes)),
+            ),
+        )
+    }
+
+    fn validate_positions<'s>(
+        &self,
+        positions: impl Iterator<Item = (&'s String, &'s Vec1<Scope>)>,
+    ) -> Result<(), NovelError> {
+        for (scene, scopes) in positions {
+            match self.find_node(scene, scopes) {
+                Ok(_) | Err(NovelError::NotStarted) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves a loaded state to the nodes it was saved at, found by their ids,
+    /// so saves keep working after the script was edited around them.
+    /// Fails if one of those nodes was removed or changed.
+    /// The rollback history is dropped when it points at nodes that are gone.
+    pub fn relocate(&self, state: &mut NovelState) -> Result<(), NovelError> {
+        self.relocate_position(&state.scene, &mut state.scopes)?;
+        for frame in &mut state.calls {
+            self.relocate_position(&frame.scene, &mut frame.scopes)?;
+        }
+        let mut rollback = std::mem::take(&mut state.rollback);
+        for snapshot in &mut rollback {
+            let frames = std::iter::once(&mut snapshot.position).chain(&mut snapshot.calls);
+            for frame in frames {
+                if self
+                    .relocate_position(&frame.scene, &mut frame.scopes)
+                    .is_err()
+                {
+                    return Ok(());
                 }
             }
-            SceneNode::Control(SceneNodeControl::If {
-                cond: if_cond,
-                else_ifs,
-                else_content,
-                content: if_content,
-            })
-        }
-        Rule::dialogue_statement => {
-            let mut diag_it = pair.into_inner();
-            let speaker = diag_it.next().unwrap().as_str().to_owned();
-            let content = diag_it.next().unwrap().as_str().to_owned();
-            SceneNode::User(SceneNodeUser::Data(SceneNodeData::Text {
-                speaker: if speaker == "_" { None } else { Some(speaker) },
-                content,
-            }))
         }
-        Rule::scene_statement => {
-            let mut scene_it = pair.into_inner();
-            let name = scene_it.next().unwrap().as_str().to_owned();
-            SceneNode::User(SceneNodeUser::Load(SceneNodeLoad::Background { name }))
-        }
-        Rule::load_statement => {
-            let mut load_it = pair.into_inner();
-            let character = load_it.next().unwrap().as_str().to_owned();
-            let mut property_list = load_it.next().unwrap().into_inner();
-            let mut properties = HashMap::new();
-            while let Some(property) = property_list.next() {
-                let mut property = property.into_inner();
-                let key = property.next().unwrap().as_str();
-                let value = property.next().unwrap().as_str();
-                properties.insert(key, value);
+        state.rollback = rollback;
+        Ok(())
+    }
+
+    fn relocate_position(&self, scene: &str, scopes: &mut [Scope]) -> Result<(), NovelError> {
+        let content = self.scene(scene)?;
+        relocate_scopes(content, scopes).map_err(|id| NovelError::IncompatibleState {
+            scene: scene.to_owned(),
+            id,
+        })
+    }
+
+    /// The options of a menu that can be picked right now, together with their 1-based index.
+    fn visible_options<'a>(
+        &self,
+        state: &NovelState,
+        menu: &SceneNode,
+        options: &'a [MenuOption],
+    ) -> Result<Vec<(i32, &'a MenuOption)>, NovelError> {
+        let mut visible = Vec::new();
+        for (n, option) in options.iter().enumerate() {
+            if option.once && state.picked_once.contains(&PickedOption::new(menu, n)) {
+                continue;
             }
-            if let Some((&key, _)) = properties
-                .iter()
-                .find(|(&key, _)| key != "expression" && key != "placement")
-            {
-                panic!("Unknown load property key {}", key);
+            if let Some(cond) = &option.condition {
+                if !cond.check(state, self.undefined_variables)? {
+                    continue;
+                }
             }
-            SceneNode::User(SceneNodeUser::Load(SceneNodeLoad::Character {
-                character,
-                expression: properties.get("expression").copied().map(String::from),
-                placement: properties.get("placement").copied().map(String::from),
-            }))
+            visible.push((n as i32 + 1, option));
         }
-        Rule::sound_statement => {
-            let mut sound_it = pair.into_inner();
-            let name = sound_it.next().unwrap().as_str().to_owned();
-            let channel = sound_it.next().unwrap().as_str().to_owned();
-            SceneNode::User(SceneNodeUser::Load(SceneNodeLoad::PlaySound {
-                name,
-                channel,
-            }))
+        Ok(visible)
+    }
+
+    /// Finds the node the state is currently at.
+    fn active_node<'a>(&'a self, state: &NovelState) -> Result<Option<&'a SceneNode>, NovelError> {
+        self.find_node(&state.scene, &state.scopes)
+    }
+
+    /// Finds the node that `scopes` point at in `scene`.
+    fn find_node<'a>(
+        &'a self,
+        scene: &str,
+        scopes: &Vec1<Scope>,
+    ) -> Result<Option<&'a SceneNode>, NovelError> {
+        let mut content = self.scene(scene)?;
+
+        let last = scopes.last();
+        let parents = &scopes[..scopes.len() - 1];
+        for (depth, scope) in parents.iter().enumerate() {
+            let invalid = || NovelError::InvalidScope {
+                scene: scene.to_owned(),
+                depth,
+            };
+            content = match scope.index.and_then(|index| content.get(index)) {
+                Some(SceneNode {
+                    kind: SceneNodeKind::Control(node),
+                    ..
+                }) => scope
+                    .branch
+                    .and_then(|branch| node.branch_content(branch))
+                    .ok_or_else(invalid)?,
+                _ => return Err(invalid()),
+            };
         }
-        Rule::remove_statement => {
-            let mut remove_it = pair.into_inner();
-            let name = remove_it.next().unwrap().as_str().to_owned();
-            SceneNode::User(SceneNodeUser::Load(SceneNodeLoad::RemoveCharacter { name }))
-        }
-        Rule::jump_statement => {
-            let mut jump_it = pair.into_inner();
-            let target = jump_it.next().unwrap().as_str().to_owned();
-            SceneNode::Control(SceneNodeControl::Jump(target))
-        }
-        Rule::set_statement => {
-            let mut set_it = pair.into_inner();
-            let character = set_it.next().unwrap().as_str().to_owned();
-            let key = set_it.next().unwrap().as_str();
-            let value = set_it.next().unwrap().as_str();
-            /* really ugly really bad but it's the easiest way of writing that I could think if */
-            let mut properties = HashMap::new();
-            properties.insert(key, value);
-            SceneNode::User(SceneNodeUser::Load(SceneNodeLoad::Character {
-                character,
-                expression: properties.get("expression").copied().map(String::from),
-                placement: properties.get("placement").copied().map(String::from),
-            }))
+
+        match last.index {
+            // One past the last node is where a finished scope is
+            Some(index) if index <= content.len() => Ok(content.get(index)),
+            None if parents.is_empty() => Err(NovelError::NotStarted),
+            _ => Err(NovelError::InvalidScope {
+                scene: scene.to_owned(),
+                depth: parents.len(),
+            }),
         }
-        _ => unreachable!(),
     }
-}
 
-fn parse(data: &str) -> Vec<SceneNode> {
-    let mut nodes = Vec::new();
+    /// Returns where the node last returned by [`Novel::next`] or [`Novel::current`] was parsed from.
+    pub fn current_span<'a>(&'a self, state: &NovelState) -> Result<Option<&'a Span>, NovelError> {
+        Ok(self.active_node(state)?.map(|node| &node.span))
+    }
 
-    let parse = NovelscriptParser::parse(Rule::file, &data)
-        .unwrap()
-        .next()
-        .unwrap();
+    fn jump(&self, state: &mut NovelState, target: &JumpTarget) -> Result<(), NovelError> {
+        let scene = self.scene(&target.scene)?;
+        state.scopes = match &target.label {
+            Some(label) => find_label(scene, label)
+                .and_then(|scopes| Vec1::try_from_vec(scopes).ok())
+                .ok_or_else(|| NovelError::LabelNotFound {
+                    scene: target.scene.clone(),
+                    label: label.clone(),
+                })?,
+            None => Vec1::new(Scope::default()),
+        };
+        state.scene = target.scene.clone();
+        Ok(())
+    }
+
+    fn fill_text(&self, template: &str, state: &NovelState) -> Result<String, NovelError> {
+        interpolate::interpolate(template, |name| match state.script_variable(name) {
+            Some(value) => Ok(value),
+            None => self.undefined_variables.undefined(name),
+        })
+    }
 
-    for line in parse.into_inner() {
-        match line.as_rule() {
-            Rule::statement => {
-                nodes.push(parse_statement(line.into_inner().next().unwrap()));
+    /// Fills in the `{variable}` placeholders of a text or choice node.
+    fn interpolate(
+        &self,
+        node: &SceneNodeUser,
+        state: &NovelState,
+    ) -> Result<SceneNodeUser, NovelError> {
+        let fill = |template: &str| self.fill_text(template, state);
+        Ok(match node {
+            SceneNodeUser::Data(SceneNodeData::Text { speaker, content }) => {
+                SceneNodeUser::Data(SceneNodeData::Text {
+                    speaker: speaker.clone(),
+                    content: fill(content)?,
+                })
+            }
+            SceneNodeUser::Data(SceneNodeData::Choice(choices)) => {
+                let choices = choices
+                    .iter()
+                    .map(|choice| {
+                        Ok(ChoiceOption {
+                            text: fill(&choice.text)?,
+                            ..choice.clone()
+                        })
+                    })
+                    .collect::<Result<_, NovelError>>()?;
+                SceneNodeUser::Data(SceneNodeData::Choice(choices))
             }
-            _ => unreachable!(),
+            SceneNodeUser::Load(_) => node.clone(),
+        })
+    }
+
+    pub fn current(&self, state: &mut NovelState) -> Result<Option<SceneNodeUser>, NovelError> {
+        let node = self.advance(state);
+        if let Ok(scene) = self.scene(&state.scene) {
+            anchor(scene, &mut state.scopes);
         }
+        node
     }
 
-    nodes
+    /// Runs control nodes until the state is at a node for the user.
+    fn advance(&self, state: &mut NovelState) -> Result<Option<SceneNodeUser>, NovelError> {
+        loop {
+            let node = self.active_node(state)?;
+            match node.map(|node| &node.kind) {
+                Some(SceneNodeKind::User(node)) => return self.interpolate(node, state).map(Some),
+                Some(SceneNodeKind::Control(control)) => match control {
+                    SceneNodeControl::Menu { id, options, .. } => {
+                        let choices = self
+                            .visible_options(state, node.unwrap(), options)?
+                            .into_iter()
+                            .map(|(index, option)| ChoiceOption {
+                                index,
+                                text: option.text.clone(),
+                                previously_picked: state.has_picked(id, index),
+                            })
+                            .collect::<Vec<_>>();
+                        // A menu without any available options is skipped
+                        if !choices.is_empty() {
+                            let node = SceneNodeUser::Data(SceneNodeData::Choice(choices));
+                            return self.interpolate(&node, state).map(Some);
+                        }
+                    }
+                    SceneNodeControl::If {
+                        cond,
+                        content: _,
+                        else_ifs,
+                        else_content,
+                    } => {
+                        let policy = self.undefined_variables;
+                        let mut branch = None;
+                        if cond.check(state, policy)? {
+                            branch = Some(Branch::First);
+                        } else {
+                            for (n, (else_if_cond, _)) in else_ifs.iter().enumerate() {
+                                if else_if_cond.check(state, policy)? {
+                                    branch = Some(Branch::Middle(n));
+                                    break;
+                                }
+                            }
+                            if branch.is_none() && else_content.is_some() {
+                                branch = Some(Branch::Last);
+                            }
+                        }
+
+                        if let Some(branch) = branch {
+                            state.scopes.last_mut().branch = Some(branch);
+                            state.scopes.push(Scope::default());
+                        }
+                    }
+                    SceneNodeControl::Jump(target) => self.jump(state, target)?,
+                    SceneNodeControl::Call(target) => {
+                        let mut frame = Frame {
+                            scene: state.scene.clone(),
+                            scopes: state.scopes.clone(),
+                        };
+                        if let Ok(scene) = self.scene(&frame.scene) {
+                            anchor(scene, &mut frame.scopes);
+                        }
+                        self.jump(state, target)?;
+                        state.calls.push(frame);
+                    }
+                    SceneNodeControl::Return => match state.calls.pop() {
+                        Some(frame) => {
+                            state.scene = frame.scene;
+                            state.scopes = frame.scopes;
+                        }
+                        None => {
+                            // Move past the end of the scene so that every next returns None
+                            let len = self.scene(&state.scene).map_or(0, <[_]>::len);
+                            state.scopes = Vec1::new(Scope {
+                                index: Some(len),
+                                ..Scope::default()
+                            });
+                            return Ok(None);
+                        }
+                    },
+                    SceneNodeControl::Label(_) => {}
+                    SceneNodeControl::Assign {
+                        variable,
+                        op,
+                        value,
+                    } => {
+                        let policy = self.undefined_variables;
+                        let mut value = value.eval(state, policy)?;
+                        if let Some(op) = op {
+                            let current = match state.script_variable(variable) {
+                                Some(current) => current,
+                                None => policy.undefined(variable)?,
+                            };
+                            value = op.apply(current, value)?;
+                        }
+                        state.variables.insert(variable.clone(), value);
+                    }
+                },
+                None => {
+                    if state.scopes.pop().is_ok() {
+                        state.scopes.last_mut().branch = None;
+                    } else if let Some(frame) = state.calls.pop() {
+                        state.scene = frame.scene;
+                        state.scopes = frame.scopes;
+                    } else {
+                        return Ok(None);
+                    }
+                }
+            }
+            state.scopes.last_mut().inc();
+        }
+    }
 }
@@ -0,0 +1,740 @@
+use crate::{
+    interpolate, read, BinaryOp, Comparison, Condition, Expr, JumpTarget, MenuOption, ParseError,
+    SceneNode, SceneNodeControl, SceneNodeData, SceneNodeKind, SceneNodeLoad, SceneNodeUser,
+    SourceLocation, Span, Value,
+};
+use pest::Parser;
+use pest_derive::Parser;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Parser)]
+#[grammar = "novelscript.pest"]
+struct NovelscriptParser;
+
+type Pair<'i> = pest::iterators::Pair<'i, Rule>;
+type Pairs<'i> = pest::iterators::Pairs<'i, Rule>;
+
+struct Context<'a> {
+    scene: &'a str,
+    source: &'a str,
+    /// Added to the positions of pairs when only a part of `source` was parsed.
+    offset: usize,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Context<'a> {
+    fn location(&self, pair: &Pair<'_>) -> SourceLocation {
+        SourceLocation::new(
+            self.scene,
+            self.source,
+            self.offset + pair.as_span().start(),
+        )
+    }
+
+    /// `offset` is where in the source the failed parse was started from,
+    /// the error is reported no further than `limit`.
+    fn syntax_error(
+        &self,
+        err: pest::error::Error<Rule>,
+        offset: usize,
+        limit: usize,
+    ) -> ParseError {
+        let pos = match err.location {
+            pest::error::InputLocation::Pos(pos) => pos,
+            pest::error::InputLocation::Span((start, _)) => start,
+        };
+        let message = match &err.variant {
+            pest::error::ErrorVariant::ParsingError { positives, .. } if !positives.is_empty() => {
+                let mut expected: Vec<String> = Vec::new();
+                for rule in positives {
+                    let description = describe_rule(rule);
+                    if !expected.contains(&description) {
+                        expected.push(description);
+                    }
+                }
+                match expected.split_last() {
+                    Some((last, [])) => format!("expected {}", last),
+                    Some((last, rest)) => format!("expected {} or {}", rest.join(", "), last),
+                    None => unreachable!(),
+                }
+            }
+            variant => variant.message().into_owned(),
+        };
+        ParseError::Syntax {
+            location: SourceLocation::new(self.scene, self.source, (offset + pos).min(limit)),
+            message,
+        }
+    }
+
+    /// Finds out why the statement at `pair` failed to parse by parsing it again on its own.
+    fn invalid_statement_error(&self, pair: &Pair<'_>) -> ParseError {
+        let start = pair.as_span().start();
+        match NovelscriptParser::parse(Rule::statement, &self.source[start..]) {
+            Err(err) => self.syntax_error(err, start, pair.as_span().end()),
+            Ok(_) => ParseError::Syntax {
+                location: self.location(pair),
+                message: format!("unexpected '{}'", pair.as_str().trim()),
+            },
+        }
+    }
+
+    /// Parses every valid statement in the list, errors are collected and the statement is skipped.
+    fn parse_statements(&mut self, pairs: Pairs<'_>) -> Vec<SceneNode> {
+        let mut nodes = Vec::new();
+        for pair in pairs {
+            let result = match pair.as_rule() {
+                Rule::statement => {
+                    let mut statement_it = pair.into_inner();
+                    let mut statement = statement_it.next().unwrap();
+                    // Nodes without a tag get an id from assign_ids once the scene is parsed
+                    let mut id = String::new();
+                    if statement.as_rule() == Rule::node_tag {
+                        id = statement.as_str()[1..].to_owned();
+                        statement = statement_it.next().unwrap();
+                    }
+                    let span = self.span(&statement);
+                    self.parse_statement(statement)
+                        .map(|kind| SceneNode { kind, span, id })
+                }
+                Rule::invalid_statement => Err(self.invalid_statement_error(&pair)),
+                Rule::unexpected_block_keyword => Err(ParseError::Syntax {
+                    location: self.location(&pair),
+                    message: format!(
+                        "unexpected '{}' outside of an if statement or choice menu",
+                        pair.as_str().split_whitespace().next().unwrap()
+                    ),
+                }),
+                _ => unreachable!(),
+            };
+            match result {
+                Ok(node) => nodes.push(node),
+                Err(err) => self.errors.push(err),
+            }
+        }
+        nodes
+    }
+
+    fn parse_if(
+        &mut self,
+        mut pair_it: Pairs<'_>,
+    ) -> Result<(Condition, Vec<SceneNode>), ParseError> {
+        let condition = self.parse_condition(pair_it.next().unwrap())?;
+        let statement_list = self.parse_statements(pair_it.next().unwrap().into_inner());
+
+        Ok((condition, statement_list))
+    }
+
+    fn parse_condition(&self, pair: Pair<'_>) -> Result<Condition, ParseError> {
+        Ok(match pair.as_rule() {
+            Rule::condition | Rule::and_condition => {
+                let is_or = pair.as_rule() == Rule::condition;
+                let mut pair_it = pair.into_inner();
+                let mut first = self.parse_condition(pair_it.next().unwrap())?;
+                while let Some(_op) = pair_it.next() {
+                    let second = Box::new(self.parse_condition(pair_it.next().unwrap())?);
+                    first = if is_or {
+                        Condition::Or(Box::new(first), second)
+                    } else {
+                        Condition::And(Box::new(first), second)
+                    };
+                }
+                first
+            }
+            Rule::not_condition => {
+                let mut pair_it = pair.into_inner().rev();
+                let mut cond = self.parse_condition(pair_it.next().unwrap())?;
+                for _ in pair_it {
+                    cond = Condition::Not(Box::new(cond));
+                }
+                cond
+            }
+            Rule::comparison => {
+                let mut pair_it = pair.into_inner();
+                let first = pair_it.next().unwrap();
+                match pair_it.next() {
+                    Some(compare_pair) => {
+                        let compare = match compare_pair.as_str() {
+                            "=" | "==" => Comparison::Equals,
+                            "!=" => Comparison::NotEquals,
+                            ">" => Comparison::MoreThan,
+                            "<" => Comparison::LessThan,
+                            ">=" => Comparison::MoreThanOrEquals,
+                            "<=" => Comparison::LessThanOrEquals,
+                            c => {
+                                return Err(ParseError::UnknownComparison {
+                                    location: self.location(&compare_pair),
+                                    operator: c.to_owned(),
+                                })
+                            }
+                        };
+                        Condition::Compare {
+                            first: self.parse_expression(first)?,
+                            compare,
+                            second: self.parse_expression(pair_it.next().unwrap())?,
+                        }
+                    }
+                    None => match parenthesized_condition(&first) {
+                        Some(cond) => self.parse_condition(cond)?,
+                        None => Condition::Truthy(self.parse_expression(first)?),
+                    },
+                }
+            }
+            Rule::chose_condition => {
+                let mut pair_it = pair.into_inner().skip(1);
+                let id = pair_it.next().unwrap().as_str().to_owned();
+                let number = pair_it.next().unwrap();
+                let choice = number
+                    .as_str()
+                    .parse()
+                    .map_err(|_| ParseError::NumberOutOfRange {
+                        location: self.location(&number),
+                        number: number.as_str().to_owned(),
+                    })?;
+                Condition::Chose { id, choice }
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    fn parse_expression(&self, pair: Pair<'_>) -> Result<Expr, ParseError> {
+        Ok(match pair.as_rule() {
+            Rule::expression | Rule::product => {
+                let mut pair_it = pair.into_inner();
+                let mut lhs = self.parse_expression(pair_it.next().unwrap())?;
+                while let Some(op) = pair_it.next() {
+                    let op = match op.as_str() {
+                        "+" => BinaryOp::Add,
+                        "-" => BinaryOp::Subtract,
+                        "*" => BinaryOp::Multiply,
+                        "/" => BinaryOp::Divide,
+                        "%" => BinaryOp::Remainder,
+                        _ => unreachable!(),
+                    };
+                    let rhs = self.parse_expression(pair_it.next().unwrap())?;
+                    lhs = Expr::Binary {
+                        op,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    };
+                }
+                lhs
+            }
+            Rule::unary => {
+                let mut pair_it = pair.into_inner().rev();
+                let mut expr = self.parse_expression(pair_it.next().unwrap())?;
+                for _ in pair_it {
+                    expr = Expr::Negate(Box::new(expr));
+                }
+                expr
+            }
+            Rule::number => match pair.as_str().parse() {
+                Ok(n) => Expr::Literal(Value::Int(n)),
+                Err(_) => {
+                    return Err(ParseError::NumberOutOfRange {
+                        location: self.location(&pair),
+                        number: pair.as_str().to_owned(),
+                    })
+                }
+            },
+            Rule::float => Expr::Literal(Value::Float(pair.as_str().parse().unwrap())),
+            Rule::boolean => Expr::Literal(Value::Bool(pair.as_str() == "true")),
+            Rule::string => Expr::Literal(Value::String(unescape(pair.as_str()))),
+            Rule::identifier => Expr::Variable(pair.as_str().to_owned()),
+            Rule::condition => match condition_expression(&pair) {
+                Some(expr) => self.parse_expression(expr)?,
+                None => {
+                    return Err(ParseError::Syntax {
+                        location: self.location(&pair),
+                        message: "expected an expression but found a condition".into(),
+                    })
+                }
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    /// Parses an option of a `[a / b]` choice or of a choice menu.
+    fn parse_option(&mut self, pair: Pair<'_>) -> Result<MenuOption, ParseError> {
+        let mut option = MenuOption {
+            text: String::new(),
+            condition: None,
+            once: false,
+            content: Vec::new(),
+        };
+        for part in pair.into_inner() {
+            match part.as_rule() {
+                Rule::once_keyword => option.once = true,
+                Rule::choice_text => option.text = self.parse_text(&part, part.as_str().trim())?,
+                Rule::string => option.text = self.parse_text(&part, &unescape(part.as_str()))?,
+                Rule::choice_guard => option.condition = Some(self.parse_guard(&part)?),
+                Rule::condition => option.condition = Some(self.parse_condition(part)?),
+                Rule::statement_list => option.content = self.parse_statements(part.into_inner()),
+                _ => {}
+            }
+        }
+        Ok(option)
+    }
+
+    /// Parses the guard of a `[a / b]` option, it can't be part of the statement's rule
+    /// since the '/' between options would be read as a division.
+    fn parse_guard(&self, pair: &Pair<'_>) -> Result<Condition, ParseError> {
+        let start = self.offset + pair.as_span().start();
+        let mut guard = NovelscriptParser::parse(Rule::guard, pair.as_str())
+            .map_err(|err| self.syntax_error(err, start, start + pair.as_str().len()))?;
+        let ctx = Context {
+            scene: self.scene,
+            source: self.source,
+            offset: start,
+            errors: Vec::new(),
+        };
+        ctx.parse_condition(guard.next().unwrap().into_inner().next().unwrap())
+    }
+
+    /// Checks the `{variable}` placeholders of a text.
+    fn parse_text(&self, pair: &Pair<'_>, text: &str) -> Result<String, ParseError> {
+        interpolate::validate(text).map_err(|message| ParseError::Syntax {
+            location: self.location(pair),
+            message,
+        })?;
+        Ok(text.to_owned())
+    }
+
+    fn collect_labels(&mut self, nodes: &[SceneNode], labels: &mut Vec<String>) {
+        for node in nodes {
+            if let SceneNodeKind::Control(control) = &node.kind {
+                if let SceneNodeControl::Label(name) = control {
+                    if labels.contains(name) {
+                        let err = ParseError::DuplicateLabel {
+                            location: SourceLocation::new(self.scene, self.source, node.span.start),
+                            label: name.clone(),
+                        };
+                        self.errors.push(err);
+                    }
+                    labels.push(name.clone());
+                }
+                for (_, content) in control.branches() {
+                    self.collect_labels(content, labels);
+                }
+            }
+        }
+    }
+
+    /// Points jumps and calls at labels of this scene and checks that they exist.
+    /// Checks the labels and jumps and gives every node an id, once all nodes are parsed.
+    fn finish(&mut self, nodes: &mut [SceneNode]) {
+        let mut labels = Vec::new();
+        self.collect_labels(nodes, &mut labels);
+        self.resolve_jumps(nodes, &labels);
+        self.assign_ids(nodes, &mut HashSet::new(), &mut HashMap::new());
+    }
+
+    fn resolve_jumps(&mut self, nodes: &mut [SceneNode], labels: &[String]) {
+        for node in nodes {
+            let control = match &mut node.kind {
+                SceneNodeKind::Control(control) => control,
+                SceneNodeKind::User(_) => continue,
+            };
+            match control {
+                SceneNodeControl::Jump(target) | SceneNodeControl::Call(target) => match &target
+                    .label
+                {
+                    None if labels.contains(&target.scene) => {
+                        target.label =
+                            Some(std::mem::replace(&mut target.scene, self.scene.to_owned()));
+                    }
+                    Some(label) if target.scene == self.scene && !labels.contains(label) => {
+                        let err = ParseError::UnknownLabel {
+                            location: SourceLocation::new(self.scene, self.source, node.span.start),
+                            label: label.clone(),
+                        };
+                        self.errors.push(err);
+                    }
+                    _ => {}
+                },
+                control => {
+                    for content in control.branches_mut() {
+                        self.resolve_jumps(content, labels);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Gives every node without a tag an id made from a hash of its first line,
+    /// nodes with the same first line are told apart by how many came before them.
+    fn assign_ids(
+        &mut self,
+        nodes: &mut [SceneNode],
+        tags: &mut HashSet<String>,
+        hashes: &mut HashMap<String, usize>,
+    ) {
+        for node in nodes {
+            if node.id.is_empty() {
+                let content = match &node.kind {
+                    // The first line of a menu is only its keyword
+                    SceneNodeKind::Control(SceneNodeControl::Menu { options, .. }) => options
+                        .iter()
+                        .map(|option| option.text.as_str())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    // Nodes that weren't parsed from a script have no source to take the first line of
+                    kind if self.source.is_empty() => summary(kind),
+                    _ => self.source[node.span.start..node.span.end]
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_owned(),
+                };
+                let hash = format!("{:016x}", read::fnv1a(content.as_bytes()));
+                let count = hashes.entry(hash.clone()).or_default();
+                *count += 1;
+                node.id = match *count {
+                    1 => hash,
+                    n => format!("{}-{}", hash, n),
+                };
+            } else if !tags.insert(node.id.clone()) {
+                let err = ParseError::DuplicateNodeId {
+                    location: SourceLocation::new(self.scene, self.source, node.span.start),
+                    id: node.id.clone(),
+                };
+                self.errors.push(err);
+            }
+            if let SceneNodeKind::Control(SceneNodeControl::Menu { id, .. }) = &mut node.kind {
+                if id.is_empty() {
+                    *id = format!("{}:{}", self.scene, node.id);
+                }
+            }
+            if let SceneNodeKind::Control(control) = &mut node.kind {
+                for content in control.branches_mut() {
+                    self.assign_ids(content, tags, hashes);
+                }
+            }
+        }
+    }
+
+    fn span(&self, pair: &Pair<'_>) -> Span {
+        let (line, column) = pair.line_col();
+        Span {
+            scene: self.scene.to_owned(),
+            start: pair.as_span().start(),
+            end: pair.as_span().end(),
+            line,
+            column,
+        }
+    }
+
+    fn parse_statement(&mut self, pair: Pair<'_>) -> Result<SceneNodeKind, ParseError> {
+        Ok(match pair.as_rule() {
+            Rule::choice_statement | Rule::menu_statement => {
+                let inline = pair.as_rule() == Rule::choice_statement;
+                // Choices without an id get one from their options in `assign_ids`
+                let mut id = String::new();
+                let mut options = Vec::new();
+                for case in pair.into_inner() {
+                    match case.as_rule() {
+                        Rule::identifier => id = case.as_str().to_owned(),
+                        Rule::choice_option | Rule::option_case => {
+                            options.push(self.parse_option(case)?)
+                        }
+                        _ => {}
+                    }
+                }
+                SceneNodeKind::Control(SceneNodeControl::Menu {
+                    id,
+                    options,
+                    inline,
+                })
+            }
+            Rule::if_statement => {
+                let mut pairs_it = pair.into_inner();
+                let (if_cond, if_content) = self.parse_if(pairs_it.next().unwrap().into_inner())?;
+                let mut else_content = None;
+                let mut else_ifs = Vec::new();
+                for case in pairs_it {
+                    match case.as_rule() {
+                        Rule::else_if_case => {
+                            let pair_it = case.into_inner().next().unwrap().into_inner();
+                            else_ifs.push(self.parse_if(pair_it)?);
+                        }
+                        Rule::else_case => {
+                            let statement_it = case.into_inner().next().unwrap().into_inner();
+                            else_content = Some(self.parse_statements(statement_it));
+                        }
+                        Rule::end_keyword => {}
+                        _ => unreachable!(),
+                    }
+                }
+                SceneNodeKind::Control(SceneNodeControl::If {
+                    cond: if_cond,
+                    else_ifs,
+                    else_content,
+                    content: if_content,
+                })
+            }
+            Rule::dialogue_statement => {
+                let mut diag_it = pair.into_inner();
+                let speaker = diag_it.next().unwrap().as_str().to_owned();
+                let content = diag_it.next().unwrap();
+                let content = self.parse_text(&content, content.as_str())?;
+                SceneNodeKind::User(SceneNodeUser::Data(SceneNodeData::Text {
+                    speaker: if speaker == "_" { None } else { Some(speaker) },
+                    content,
+                }))
+            }
+            Rule::scene_statement => {
+                let mut scene_it = pair.into_inner();
+                let name = scene_it.next().unwrap().as_str().to_owned();
+                SceneNodeKind::User(SceneNodeUser::Load(SceneNodeLoad::Background { name }))
+            }
+            Rule::load_statement => {
+                let mut load_it = pair.into_inner();
+                let character = load_it.next().unwrap().as_str().to_owned();
+                let property_list = load_it.next().unwrap().into_inner();
+                let mut properties = HashMap::new();
+                for property in property_list {
+                    let mut property_it = property.clone().into_inner();
+                    let key = property_it.next().unwrap().as_str();
+                    let value = property_it.next().unwrap().as_str();
+                    if key != "expression" && key != "placement" {
+                        return Err(ParseError::UnknownLoadProperty {
+                            location: self.location(&property),
+                            key: key.to_owned(),
+                        });
+                    }
+                    properties.insert(key, value);
+                }
+                SceneNodeKind::User(SceneNodeUser::Load(SceneNodeLoad::Character {
+                    character,
+                    expression: properties.get("expression").copied().map(String::from),
+                    placement: properties.get("placement").copied().map(String::from),
+                }))
+            }
+            Rule::sound_statement => {
+                let mut sound_it = pair.into_inner();
+                let name = sound_it.next().unwrap().as_str().to_owned();
+                let channel = sound_it.next().unwrap().as_str().to_owned();
+                SceneNodeKind::User(SceneNodeUser::Load(SceneNodeLoad::PlaySound {
+                    name,
+                    channel,
+                }))
+            }
+            Rule::remove_statement => {
+                let mut remove_it = pair.into_inner();
+                let name = remove_it.next().unwrap().as_str().to_owned();
+                SceneNodeKind::User(SceneNodeUser::Load(SceneNodeLoad::RemoveCharacter { name }))
+            }
+            Rule::jump_statement => {
+                let mut jump_it = pair.into_inner();
+                let target = jump_target(jump_it.next().unwrap().as_str());
+                SceneNodeKind::Control(SceneNodeControl::Jump(target))
+            }
+            Rule::call_statement => {
+                let target = jump_target(pair.into_inner().nth(1).unwrap().as_str());
+                SceneNodeKind::Control(SceneNodeControl::Call(target))
+            }
+            Rule::return_statement => SceneNodeKind::Control(SceneNodeControl::Return),
+            Rule::label_statement => {
+                let name = pair.into_inner().nth(1).unwrap().as_str().to_owned();
+                SceneNodeKind::Control(SceneNodeControl::Label(name))
+            }
+            Rule::assign_statement => {
+                let mut assign_it = pair.into_inner().peekable();
+                assign_it.next_if(|pair| pair.as_rule() == Rule::var_keyword);
+                let variable = assign_it.next().unwrap().as_str().to_owned();
+                let op = match assign_it.next().unwrap().as_str() {
+                    "=" => None,
+                    "+=" => Some(BinaryOp::Add),
+                    "-=" => Some(BinaryOp::Subtract),
+                    "*=" => Some(BinaryOp::Multiply),
+                    "/=" => Some(BinaryOp::Divide),
+                    "%=" => Some(BinaryOp::Remainder),
+                    _ => unreachable!(),
+                };
+                let value = self.parse_expression(assign_it.next().unwrap())?;
+                SceneNodeKind::Control(SceneNodeControl::Assign {
+                    variable,
+                    op,
+                    value,
+                })
+            }
+            Rule::set_statement => {
+                let mut set_it = pair.into_inner();
+                let character = set_it.next().unwrap().as_str().to_owned();
+                let key = set_it.next().unwrap().as_str();
+                let value = set_it.next().unwrap().as_str();
+                /* really ugly really bad but it's the easiest way of writing that I could think if */
+                let mut properties = HashMap::new();
+                properties.insert(key, value);
+                SceneNodeKind::User(SceneNodeUser::Load(SceneNodeLoad::Character {
+                    character,
+                    expression: properties.get("expression").copied().map(String::from),
+                    placement: properties.get("placement").copied().map(String::from),
+                }))
+            }
+            _ => unreachable!(),
+        })
+    }
+}
+
+/// Removes the quotes around a string literal and resolves its escape sequences.
+fn unescape(literal: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = literal[1..literal.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c) => c,
+                None => break,
+            },
+            c => c,
+        });
+    }
+    unescaped
+}
+
+/// Splits `scene.label`, bare targets are turned into local labels by `resolve_jumps` if one exists.
+fn jump_target(target: &str) -> JumpTarget {
+    match target.rsplit_once('.') {
+        Some((scene, label)) => JumpTarget {
+            scene: scene.to_owned(),
+            label: Some(label.to_owned()),
+        },
+        None => JumpTarget {
+            scene: target.to_owned(),
+            label: None,
+        },
+    }
+}
+
+/// The only child of `pair`, if it has exactly one.
+fn only_child<'i>(pair: &Pair<'i>) -> Option<Pair<'i>> {
+    let mut pair_it = pair.clone().into_inner();
+    match (pair_it.next(), pair_it.next()) {
+        (Some(child), None) => Some(child),
+        _ => None,
+    }
+}
+
+/// Returns the condition inside of `(...)` if that is all the expression is made of.
+fn parenthesized_condition<'i>(expression: &Pair<'i>) -> Option<Pair<'i>> {
+    let product = only_child(expression)?;
+    let unary = only_child(&product)?;
+    only_child(&unary).filter(|pair| pair.as_rule() == Rule::condition)
+}
+
+/// Returns the expression a condition is made of if it doesn't use any comparisons or logic.
+fn condition_expression<'i>(condition: &Pair<'i>) -> Option<Pair<'i>> {
+    let and_condition = only_child(condition)?;
+    let not_condition = only_child(&and_condition)?;
+    let comparison = only_child(&not_condition)?;
+    only_child(&comparison).filter(|pair| pair.as_rule() == Rule::expression)
+}
+
+fn describe_rule(rule: &Rule) -> String {
+    match rule {
+        Rule::statement | Rule::invalid_statement => "a statement".into(),
+        Rule::EOI => "the end of the file".into(),
+        Rule::guard_end => "'}'".into(),
+        Rule::else_if_case => "'else if'".into(),
+        Rule::else_case => "'else'".into(),
+        Rule::end_keyword => "'end'".into(),
+        Rule::option_case | Rule::option_keyword => "'option'".into(),
+        Rule::choice_keyword => "'choice'".into(),
+        Rule::once_keyword => "'once'".into(),
+        Rule::if_keyword => "'if'".into(),
+        Rule::choice_separator | Rule::choice_end => "'/' or ']'".into(),
+        Rule::choice_guard => "a condition".into(),
+        Rule::name => "a name".into(),
+        Rule::text | Rule::choice_text => "text".into(),
+        Rule::comparison_op => "a comparison operator".into(),
+        Rule::condition
+        | Rule::and_condition
+        | Rule::not_condition
+        | Rule::comparison
+        | Rule::chose_condition => "a condition".into(),
+        Rule::and_op | Rule::or_op => "'and' or 'or'".into(),
+        Rule::load_property => "a load property".into(),
+        Rule::expression | Rule::unary | Rule::product => "an expression".into(),
+        Rule::identifier => "a variable name".into(),
+        Rule::number | Rule::float | Rule::boolean | Rule::string => "a value".into(),
+        Rule::add_op | Rule::mul_op => "an operator".into(),
+        Rule::assign_op => "':' or '='".into(),
+        rule => format!("{:?}", rule),
+    }
+}
+
+/// Parses the source of a scene, `scene` is used for error reporting and to resolve its labels.
+/// Returns the first error in the scene, see [`parse_recovering`] to get all of them.
+pub fn parse(scene: &str, data: &str) -> Result<Vec<SceneNode>, ParseError> {
+    let (nodes, mut errors) = parse_recovering(scene, data);
+    if errors.is_empty() {
+        Ok(nodes)
+    } else {
+        Err(errors.swap_remove(0))
+    }
+}
+
+/// Parses the source of a scene, skipping invalid statements instead of stopping at the first one.
+/// Returns the nodes of every valid statement together with all errors found in the scene.
+pub fn parse_recovering(scene: &str, data: &str) -> (Vec<SceneNode>, Vec<ParseError>) {
+    let mut ctx = Context {
+        scene,
+        source: data,
+        offset: 0,
+        errors: Vec::new(),
+    };
+
+    let mut nodes = match NovelscriptParser::parse(Rule::file, data) {
+        Ok(mut parse) => ctx.parse_statements(parse.next().unwrap().into_inner()),
+        Err(err) => {
+            let err = ctx.syntax_error(err, 0, data.len());
+            ctx.errors.push(err);
+            Vec::new()
+        }
+    };
+
+    ctx.finish(&mut nodes);
+    (nodes, ctx.errors)
+}
+
+/// Resolves the labels and assigns the ids of nodes that weren't parsed from a script,
+/// like parsing does. Returns the first error.
+pub(crate) fn prepare_nodes(scene: &str, nodes: &mut [SceneNode]) -> Result<(), ParseError> {
+    let mut ctx = Context {
+        scene,
+        source: "",
+        offset: 0,
+        errors: Vec::new(),
+    };
+    ctx.finish(nodes);
+    match ctx.errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// What the id of a node that wasn't parsed is made from, in place of its first line.
+fn summary(kind: &SceneNodeKind) -> String {
+    match kind {
+        SceneNodeKind::User(node) => format!("{:?}", node),
+        SceneNodeKind::Control(control) => match control {
+            SceneNodeControl::If { cond, .. } => format!("if {}", cond),
+            SceneNodeControl::Menu { id, .. } => format!("choice {}", id),
+            SceneNodeControl::Jump(target) => format!("jump {}", target),
+            SceneNodeControl::Call(target) => format!("call {}", target),
+            SceneNodeControl::Return => "return".to_owned(),
+            SceneNodeControl::Label(name) => format!("label {}", name),
+            SceneNodeControl::Assign {
+                variable,
+                op,
+                value,
+            } => match op {
+                Some(op) => format!("{} {}= {}", variable, op, value),
+                None => format!("{} = {}", variable, value),
+            },
+        },
+    }
+}
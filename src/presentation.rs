@@ -0,0 +1,60 @@
+use crate::SceneNodeLoad;
+use std::collections::BTreeMap;
+
+/// What is on screen and playing, so it can be restored straight away when a save is loaded.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Presentation {
+    pub background: Option<String>,
+    /// The characters on screen in the order they were loaded.
+    pub characters: Vec<Character>,
+    /// The sound last played on each channel.
+    pub sounds: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Character {
+    pub name: String,
+    pub expression: Option<String>,
+    pub placement: Option<String>,
+}
+
+impl Presentation {
+    pub fn character(&self, name: &str) -> Option<&Character> {
+        self.characters
+            .iter()
+            .find(|character| character.name == name)
+    }
+
+    /// Updates the presentation with a load node.
+    /// Loading a character that is already on screen only changes the properties that are given.
+    pub(crate) fn apply(&mut self, node: &SceneNodeLoad) {
+        match node {
+            SceneNodeLoad::Character {
+                character,
+                expression,
+                placement,
+            } => match self.characters.iter_mut().find(|c| c.name == *character) {
+                Some(current) => {
+                    if expression.is_some() {
+                        current.expression = expression.clone();
+                    }
+                    if placement.is_some() {
+                        current.placement = placement.clone();
+                    }
+                }
+                None => self.characters.push(Character {
+                    name: character.clone(),
+                    expression: expression.clone(),
+                    placement: placement.clone(),
+                }),
+            },
+            SceneNodeLoad::Background { name } => self.background = Some(name.clone()),
+            SceneNodeLoad::PlaySound { name, channel } => {
+                self.sounds.insert(channel.clone(), name.clone());
+            }
+            SceneNodeLoad::RemoveCharacter { name } => {
+                self.characters.retain(|character| character.name != *name)
+            }
+        }
+    }
+}
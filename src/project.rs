@@ -0,0 +1,169 @@
+use crate::{parse, source::LazyScene, LoadError, Novel, NovelState, SceneNode, Value};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+/// Describes a whole novel, loaded with [`Novel::load_manifest`].
+///
+/// ```json
+/// {
+///     "start": "intro",
+///     "scenes": ["intro.ns", "chapter1/forest.ns"],
+///     "variables": { "gold": 10 }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct Manifest {
+    /// The scene new states start in.
+    pub start: String,
+    /// The script files, relative to the manifest.
+    /// Every `.ns` file in the manifest's directory is loaded when this is left out.
+    #[serde(default)]
+    pub scenes: Option<Vec<PathBuf>>,
+    /// The variables new states start with.
+    #[serde(default)]
+    pub variables: HashMap<String, Value>,
+}
+
+impl Manifest {
+    /// A state at the start scene with the default variables set.
+    pub fn new_state(&self, novel: &Novel) -> NovelState {
+        let mut state = novel.new_state(&self.start);
+        for (name, value) in &self.variables {
+            state.set_variable(name.clone(), value.clone());
+        }
+        state
+    }
+}
+
+/// The scene name of a script, its path without the extension with folders separated by `/`.
+pub(crate) fn scene_name(relative: &Path) -> String {
+    let path = relative.with_extension("");
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Jumps read everything after a '.' as a label, so a scene with one in its name couldn't be jumped to.
+pub(crate) fn check_scene_name(name: &str) -> Result<(), LoadError> {
+    if name.contains('.') {
+        return Err(LoadError::InvalidSceneName(name.to_owned()));
+    }
+    Ok(())
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> LoadError + '_ {
+    move |source| LoadError::Io {
+        path: path.to_owned(),
+        source,
+    }
+}
+
+/// The `.ns` files under `dir` relative to `root`, sorted so scenes load in the same order everywhere.
+pub(crate) fn script_files(
+    root: &Path,
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), LoadError> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect()
+        })
+        .map_err(io_error(dir))?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            script_files(root, &path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "ns") {
+            files.push(path.strip_prefix(root).unwrap().to_owned());
+        }
+    }
+    Ok(())
+}
+
+impl Novel {
+    /// Adds every `.ns` file in `dir` and its subfolders.
+    /// Scenes are named after their path relative to `dir`, so `chapter1/forest.ns` becomes `chapter1/forest`.
+    /// Nothing is added if one of the scripts can't be loaded.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> Result<(), LoadError> {
+        let dir = dir.as_ref();
+        let mut files = Vec::new();
+        script_files(dir, dir, &mut files)?;
+        let scenes = self.read_scripts(dir, &files)?;
+        self.insert_scripts(scenes);
+        Ok(())
+    }
+
+    /// Adds the scenes of a manifest, see [`Manifest`]. Nothing is added if the manifest is invalid
+    /// or one of the scripts can't be loaded.
+    pub fn load_manifest(&mut self, path: impl AsRef<Path>) -> Result<Manifest, LoadError> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path).map_err(io_error(path))?;
+        let invalid = |message: String| LoadError::Manifest {
+            path: path.to_owned(),
+            message,
+        };
+        let manifest: Manifest =
+            serde_json::from_str(&data).map_err(|err| invalid(err.to_string()))?;
+        if manifest.variables.contains_key("choice") {
+            return Err(invalid("'choice' can't be given a default value".into()));
+        }
+
+        let root = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let files = match &manifest.scenes {
+            Some(scenes) => scenes.clone(),
+            None => {
+                let mut files = Vec::new();
+                script_files(root, root, &mut files)?;
+                files
+            }
+        };
+        let scenes = self.read_scripts(root, &files)?;
+        let start_exists = self.scenes.contains_key(&manifest.start)
+            || scenes.iter().any(|(name, _)| *name == manifest.start);
+        if !start_exists {
+            let message = format!("the start scene '{}' doesn't exist", manifest.start);
+            return Err(invalid(message));
+        }
+        self.insert_scripts(scenes);
+        Ok(manifest)
+    }
+
+    /// Reads and parses the scripts at `files` relative to `root`, checking their names
+    /// against each other and the scenes that were already added.
+    fn read_scripts(
+        &self,
+        root: &Path,
+        files: &[PathBuf],
+    ) -> Result<Vec<(String, Vec<SceneNode>)>, LoadError> {
+        let mut scenes: Vec<(String, Vec<SceneNode>)> = Vec::new();
+        for file in files {
+            let name = scene_name(file);
+            check_scene_name(&name)?;
+            if self.scenes.contains_key(&name) || scenes.iter().any(|(other, _)| *other == name) {
+                return Err(LoadError::DuplicateScene(name));
+            }
+            let path = root.join(file);
+            let data = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+            let nodes = parse(&name, &data)?;
+            scenes.push((name, nodes));
+        }
+        Ok(scenes)
+    }
+
+    fn insert_scripts(&mut self, scenes: Vec<(String, Vec<SceneNode>)>) {
+        for (name, nodes) in scenes {
+            self.scenes.insert(name, LazyScene::parsed(nodes));
+        }
+    }
+}
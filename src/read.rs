@@ -0,0 +1,55 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The lines that have been read across every save, for skipping only read text.
+///
+/// Lines are keyed by their scene and a fingerprint of their speaker and unfilled text,
+/// so a line stays read when other lines around it are edited.
+/// This is meant to be saved on its own, separately from any [`crate::NovelState`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ReadSet {
+    /// Hex encoded fingerprints of the read lines of each scene.
+    scenes: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl ReadSet {
+    pub fn new() -> Self {
+        ReadSet::default()
+    }
+
+    /// The number of lines that have been read.
+    pub fn len(&self) -> usize {
+        self.scenes.values().map(BTreeSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    /// Marks the line as read and returns whether it already was.
+    pub(crate) fn insert(&mut self, scene: &str, speaker: Option<&str>, content: &str) -> bool {
+        let fingerprint = format!("{:016x}", fingerprint(speaker, content));
+        match self.scenes.get_mut(scene) {
+            Some(lines) => !lines.insert(fingerprint),
+            None => {
+                self.scenes
+                    .insert(scene.to_owned(), std::iter::once(fingerprint).collect());
+                false
+            }
+        }
+    }
+}
+
+fn fingerprint(speaker: Option<&str>, content: &str) -> u64 {
+    let speaker = speaker.unwrap_or_default();
+    fnv1a(&[speaker.as_bytes(), &[0], content.as_bytes()].concat())
+}
+
+/// 64-bit FNV-1a, stable between runs and platforms unlike the hasher of the standard library.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
@@ -0,0 +1,165 @@
+use crate::{
+    parse,
+    project::{scene_name, script_files},
+    LoadError, NovelError, SceneNode,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+
+/// Where the scripts of scenes added with [`crate::Novel::add_source`] are read from.
+/// Scenes are only read and parsed the first time they're used.
+pub trait ScriptSource: Send + Sync {
+    /// The names of every scene in the source.
+    fn scenes(&self) -> Result<Vec<String>, LoadError>;
+
+    /// Reads the script of one of the scenes.
+    fn read(&self, scene: &str) -> Result<String, LoadError>;
+}
+
+/// The `.ns` files in a directory, named like [`crate::Novel::load_dir`] does.
+#[derive(Debug, Clone)]
+pub struct DirSource {
+    root: PathBuf,
+}
+
+impl DirSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DirSource { root: root.into() }
+    }
+}
+
+impl ScriptSource for DirSource {
+    fn scenes(&self) -> Result<Vec<String>, LoadError> {
+        let mut files = Vec::new();
+        script_files(&self.root, &self.root, &mut files)?;
+        Ok(files.iter().map(|file| scene_name(file)).collect())
+    }
+
+    fn read(&self, scene: &str) -> Result<String, LoadError> {
+        let path = self.root.join(format!("{}.ns", scene));
+        std::fs::read_to_string(&path).map_err(|source| LoadError::Io { path, source })
+    }
+}
+
+/// Scripts that are already in memory, for example embedded in the game.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    scripts: HashMap<String, String>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        MemorySource::default()
+    }
+
+    pub fn insert(&mut self, scene: String, script: String) {
+        self.scripts.insert(scene, script);
+    }
+}
+
+impl ScriptSource for MemorySource {
+    fn scenes(&self) -> Result<Vec<String>, LoadError> {
+        Ok(self.scripts.keys().cloned().collect())
+    }
+
+    fn read(&self, scene: &str) -> Result<String, LoadError> {
+        self.scripts
+            .get(scene)
+            .cloned()
+            .ok_or_else(|| LoadError::SceneNotFound(scene.to_owned()))
+    }
+}
+
+/// The `.ns` files in a zip archive, named like [`DirSource`] does.
+#[cfg(feature = "zip")]
+pub struct ZipSource<R> {
+    archive: std::sync::Mutex<zip::ZipArchive<R>>,
+}
+
+#[cfg(feature = "zip")]
+impl<R: std::io::Read + std::io::Seek> ZipSource<R> {
+    pub fn new(reader: R) -> Result<Self, LoadError> {
+        Ok(ZipSource {
+            archive: std::sync::Mutex::new(zip::ZipArchive::new(reader)?),
+        })
+    }
+}
+
+#[cfg(feature = "zip")]
+impl<R: std::io::Read + std::io::Seek + Send> ScriptSource for ZipSource<R> {
+    fn scenes(&self) -> Result<Vec<String>, LoadError> {
+        let archive = self.archive.lock().unwrap();
+        Ok(archive
+            .file_names()
+            .filter_map(|name| name.strip_suffix(".ns"))
+            .map(String::from)
+            .collect())
+    }
+
+    fn read(&self, scene: &str) -> Result<String, LoadError> {
+        use std::io::Read;
+        let path = format!("{}.ns", scene);
+        let mut archive = self.archive.lock().unwrap();
+        let mut script = String::new();
+        archive
+            .by_name(&path)?
+            .read_to_string(&mut script)
+            .map_err(|source| LoadError::Io {
+                path: path.into(),
+                source,
+            })?;
+        Ok(script)
+    }
+}
+
+/// A scene that is parsed on first use when it comes from a [`ScriptSource`].
+#[derive(Clone)]
+pub(crate) struct LazyScene {
+    source: Option<Arc<dyn ScriptSource>>,
+    nodes: OnceLock<Vec<SceneNode>>,
+}
+
+impl LazyScene {
+    pub(crate) fn parsed(nodes: Vec<SceneNode>) -> Self {
+        LazyScene {
+            source: None,
+            nodes: OnceLock::from(nodes),
+        }
+    }
+
+    pub(crate) fn from_source(source: Arc<dyn ScriptSource>) -> Self {
+        LazyScene {
+            source: Some(source),
+            nodes: OnceLock::new(),
+        }
+    }
+
+    /// The nodes of the scene, reading and parsing it if that hasn't happened yet.
+    pub(crate) fn nodes(&self, name: &str) -> Result<&[SceneNode], NovelError> {
+        if let Some(nodes) = self.nodes.get() {
+            return Ok(nodes);
+        }
+        // Only scenes from a source can be unparsed
+        let source = self.source.as_ref().unwrap();
+        let script = source.read(name).map_err(|err| NovelError::SceneRead {
+            scene: name.to_owned(),
+            message: err.to_string(),
+        })?;
+        let nodes = parse(name, &script).map_err(NovelError::SceneParse)?;
+        // Another thread might have parsed it in the meantime, either result is the same
+        let _ = self.nodes.set(nodes);
+        Ok(self.nodes.get().unwrap())
+    }
+}
+
+impl fmt::Debug for LazyScene {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyScene")
+            .field("nodes", &self.nodes.get())
+            .finish_non_exhaustive()
+    }
+}
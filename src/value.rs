@@ -0,0 +1,136 @@
+use crate::NovelError;
+use std::{cmp::Ordering, fmt};
+
+/// The value of a variable.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Int(i32),
+    Float(f64),
+    String(String),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Ints are converted to floats.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(f64::from(*n)),
+            Value::Float(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Whether the value counts as true in a condition.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Float(n) => *n != 0.0,
+            Value::String(s) => !s.is_empty(),
+        }
+    }
+
+    /// Compares two values of the same type, ints and floats can be mixed.
+    pub fn try_cmp(&self, other: &Value) -> Result<Ordering, NovelError> {
+        match (self, other) {
+            (Value::Int(first), Value::Int(second)) => Ok(first.cmp(second)),
+            (Value::String(first), Value::String(second)) => Ok(first.cmp(second)),
+            (Value::Bool(first), Value::Bool(second)) => Ok(first.cmp(second)),
+            (first, second) => match (first.as_float(), second.as_float()) {
+                (Some(first), Some(second)) => first
+                    .partial_cmp(&second)
+                    .ok_or_else(|| NovelError::TypeMismatch("can't compare NaN".into())),
+                _ => Err(NovelError::TypeMismatch(format!(
+                    "can't compare {} and {}",
+                    first.type_name(),
+                    second.type_name()
+                ))),
+            },
+        }
+    }
+}
+
+/// Formats the value the way it would be written in a script.
+pub(crate) struct Literal<'a>(pub &'a Value);
+
+impl fmt::Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::String(s) => write!(f, "{:?}", s),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::String(s) => f.write_str(s),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Float(n)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
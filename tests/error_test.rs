@@ -0,0 +1,379 @@
+#[test]
+fn test_syntax_error() {
+    let mut novel = novelscript::Novel::new();
+    let err = novel
+        .add_scene(
+            "test".into(),
+            r#"
+foo: test
+if num = 1
+    _: first
+"#,
+        )
+        .unwrap_err();
+
+    match &err {
+        novelscript::LoadError::Parse(novelscript::ParseError::Syntax { location, .. }) => {
+            assert_eq!("test", location.scene);
+            assert_eq!(3, location.line);
+        }
+        _ => panic!("Expected a syntax error, got {:?}", err),
+    }
+}
+
+#[test]
+fn test_unknown_load_property() {
+    let mut novel = novelscript::Novel::new();
+    let err = novel
+        .add_scene(
+            "test".into(),
+            r#"
+foo: test
+load Bar {
+    expression Normal
+    colour Red
+}
+"#,
+        )
+        .unwrap_err();
+    let err = match err {
+        novelscript::LoadError::Parse(err) => err,
+        err => panic!("Expected a parse error, got {:?}", err),
+    };
+
+    assert_eq!(
+        novelscript::ParseError::UnknownLoadProperty {
+            location: novelscript::SourceLocation {
+                scene: "test".into(),
+                line: 5,
+                column: 5,
+                snippet: "    colour Red".into(),
+            },
+            key: "colour".into(),
+        },
+        err
+    );
+    assert_eq!(
+        "test:5:5: unknown load property key 'colour'",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_recovering_parse() {
+    let (nodes, errors) = novelscript::parse_recovering(
+        "test",
+        r#"
+foo: test
+bar test
+if num = 1
+    oops
+    _: first
+end
+end
+_: last
+"#,
+    );
+
+    assert_eq!(3, nodes.len());
+    assert_eq!(
+        vec![(3, "bar test"), (5, "    oops"), (8, "end")],
+        errors
+            .iter()
+            .map(|err| (err.location().line, err.location().snippet.as_str()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!("expected ':' or '='", errors[0].message());
+}
+
+#[test]
+fn test_runtime_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+if num = 1
+    _: first
+end
+jump missing
+"#,
+    )?;
+
+    let mut state = novel.new_state("test");
+    assert_eq!(
+        Err(novelscript::NovelError::NotStarted),
+        novel.current(&mut state)
+    );
+    assert_eq!(
+        Err(novelscript::NovelError::UndefinedVariable("num".into())),
+        novel.next(&mut state)
+    );
+
+    let mut state = novel.new_state("test");
+    novel.set_undefined_variable_policy(novelscript::UndefinedVariablePolicy::Zero);
+    assert_eq!(
+        Err(novelscript::NovelError::SceneNotFound("missing".into())),
+        novel.next(&mut state)
+    );
+
+    let mut state = novel.new_state("nowhere");
+    assert_eq!(
+        Err(novelscript::NovelError::SceneNotFound("nowhere".into())),
+        novel.next(&mut state)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_retry_after_error() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.set_backlog_size(5);
+    novel.add_scene("test".into(), "_: first\n_: hi {name}\n")?;
+    let mut state = novel.new_state("test");
+    novel.next(&mut state)?;
+
+    // The state stays at the line that failed
+    for _ in 0..2 {
+        assert_eq!(
+            Err(novelscript::NovelError::UndefinedVariable("name".into())),
+            novel.next(&mut state)
+        );
+    }
+    assert_eq!(1, state.backlog().len());
+    state.set_variable("name".into(), "Bob");
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "hi Bob".into(),
+            }
+        )),
+        novel.next(&mut state)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_warn_undefined_variable() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+    let mut novel = novelscript::Novel::new();
+    novel.set_undefined_variable_policy(novelscript::UndefinedVariablePolicy::Warn(|name| {
+        assert_eq!("gold", name);
+        WARNINGS.fetch_add(1, Ordering::SeqCst);
+    }));
+    novel.add_scene("test".into(), "_: {gold} gold\n")?;
+    let mut state = novel.new_state("test");
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "0 gold".into(),
+            }
+        )),
+        novel.next(&mut state)?
+    );
+    assert_eq!(1, WARNINGS.load(Ordering::SeqCst));
+
+    Ok(())
+}
+
+#[test]
+fn test_condition_as_number() {
+    let mut novel = novelscript::Novel::new();
+    let err = novel
+        .add_scene(
+            "test".into(),
+            r#"
+if (trust > 1) + 1 > 2
+    _: first
+end
+"#,
+        )
+        .unwrap_err();
+
+    assert_eq!(
+        "test:2:5: expected an expression but found a condition",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_type_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+if route > 1
+    _: first
+end
+"#,
+    )?;
+
+    let mut state = novel.new_state("test");
+    state.set_variable("route".into(), "alice");
+    assert_eq!(
+        Err(novelscript::NovelError::TypeMismatch(
+            "can't compare string and int".into()
+        )),
+        novel.next(&mut state)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_interpolation_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    let err = novel
+        .add_scene("test".into(), "_: Hello, {player_name\n")
+        .unwrap_err();
+    assert_eq!(
+        "test:1:4: unclosed '{' in text, use '{{' for a literal brace",
+        err.to_string()
+    );
+
+    novel.add_scene("test".into(), "_: Hello, {player_name}!\n")?;
+    let mut state = novel.new_state("test");
+    assert_eq!(
+        Err(novelscript::NovelError::UndefinedVariable(
+            "player_name".into()
+        )),
+        novel.next(&mut state)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_label_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let (_, errors) = novelscript::parse_recovering(
+        "test",
+        r#"
+label start
+_: first
+label start
+jump test.missing
+"#,
+    );
+    assert_eq!(
+        vec![
+            "test:4:1: label 'start' is defined more than once",
+            "test:5:1: couldn't find label 'missing'"
+        ],
+        errors.iter().map(|err| err.to_string()).collect::<Vec<_>>()
+    );
+
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "jump other.missing\n")?;
+    novel.add_scene("other".into(), "_: first\n")?;
+    let mut state = novel.new_state("test");
+    assert_eq!(
+        Err(novelscript::NovelError::LabelNotFound {
+            scene: "other".into(),
+            label: "missing".into()
+        }),
+        novel.next(&mut state)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_choice() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+choice
+option "Stay"
+    _: stayed
+end
+"#,
+    )?;
+
+    let mut state = novel.new_state("test");
+    novel.next(&mut state)?;
+    assert_eq!(
+        Err(novelscript::NovelError::InvalidChoice {
+            choice: 0,
+            options: 1
+        }),
+        novel.next(&mut state)
+    );
+    state.set_choice(2);
+    assert_eq!(
+        Err(novelscript::NovelError::InvalidChoice {
+            choice: 2,
+            options: 1
+        }),
+        novel.next(&mut state)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_option_guard_error() {
+    let (_, errors) =
+        novelscript::parse_recovering("test", "_: first\n[Ask {if has_key and} / Leave]\n");
+    assert_eq!(
+        vec!["test:2:21: expected a condition"],
+        errors.iter().map(|err| err.to_string()).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_duplicate_node_id() -> Result<(), Box<dyn std::error::Error>> {
+    let (_, errors) = novelscript::parse_recovering("test", "#start _: first\n#start _: second\n");
+    assert_eq!(
+        vec!["test:2:8: node id 'start' is used more than once"],
+        errors.iter().map(|err| err.to_string()).collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_expression_line_end() -> Result<(), Box<dyn std::error::Error>> {
+    let (_, errors) = novelscript::parse_recovering("test", "x = 2\n-1\n_: {x}\n");
+    assert_eq!(
+        vec!["test:2:1: expected a statement"],
+        errors.iter().map(|err| err.to_string()).collect::<Vec<_>>()
+    );
+
+    // An operator at the end of a line still continues the expression
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "x = 1 +\n    2\n_: {x}\n")?;
+    let mut state = novel.new_state("test");
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "3".into()
+            }
+        )),
+        novel.next(&mut state)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_choice_syntax_errors() {
+    let (_, errors) = novelscript::parse_recovering(
+        "test",
+        "[x / y\n[a {if / b]\n[Ask {if she is ok} / Leave]\n",
+    );
+    assert_eq!(
+        vec![
+            "test:1:7: expected '/' or ']'",
+            "test:2:8: expected a condition",
+            "test:3:14: expected a comparison operator, '}', an operator or 'and' or 'or'"
+        ],
+        errors.iter().map(|err| err.to_string()).collect::<Vec<_>>()
+    );
+}
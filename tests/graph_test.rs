@@ -0,0 +1,225 @@
+use novelscript::{GraphNode, RevNeighbors};
+
+#[test]
+fn test_else_if_chain_graph() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+
+if num = 1
+    _: first
+else if num = 2
+    _: second
+else if num = 3
+    _: third
+else
+    _: other
+end
+
+    "#,
+    )?;
+    let (graph, root) = novel.extract_graph("test")?;
+
+    let branches = graph
+        .rev_neighbors(root)
+        .map(|branch| match &graph[branch] {
+            GraphNode::Branch(cond) => {
+                let mut children = graph.rev_neighbors(branch);
+                match (&graph[children.next().unwrap()], children.next()) {
+                    (
+                        GraphNode::Node {
+                            node:
+                                novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+                                    content,
+                                    ..
+                                }),
+                        },
+                        None,
+                    ) => format!("{} => {}", cond, content),
+                    node => panic!("Unexpected branch content {:?}", node),
+                }
+            }
+            node => panic!("Unexpected node {:?}", node),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(4, branches.len());
+    assert_eq!(
+        vec![
+            "num = 1 => first",
+            "num != 1 and num = 2 => second",
+            "num != 1 and num != 2 and num = 3 => third"
+        ],
+        branches[..3]
+    );
+    assert_eq!("num != 1 and num != 2 and num != 3 => other", branches[3]);
+
+    Ok(())
+}
+
+#[test]
+fn test_branch_guards() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+
+if num > 1
+    _: first
+else if num < 0 or not debug
+    _: second
+else
+    _: other
+end
+
+    "#,
+    )?;
+    let (graph, root) = novel.extract_graph("test")?;
+
+    let guards = graph
+        .rev_neighbors(root)
+        .map(|branch| match &graph[branch] {
+            GraphNode::Branch(cond) => cond.to_string(),
+            node => panic!("Unexpected node {:?}", node),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            "num > 1",
+            "num <= 1 and (num < 0 or not debug)",
+            "num <= 1 and num >= 0 and debug"
+        ],
+        guards
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_label_loop_graph() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+
+label top
+_: first
+if again
+    jump top
+end
+_: last
+
+    "#,
+    )?;
+    let (graph, root) = novel.extract_graph("test")?;
+
+    let texts = |parent| {
+        graph
+            .rev_neighbors(parent)
+            .filter_map(|node| match &graph[node] {
+                GraphNode::Node {
+                    node:
+                        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+                            content,
+                            ..
+                        }),
+                } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec!["first", "last"], texts(root));
+
+    // The jump continues after the label once and then stops instead of looping forever
+    let branch = graph
+        .rev_neighbors(root)
+        .find(|&node| matches!(graph[node], GraphNode::Branch(_)))
+        .unwrap();
+    assert_eq!(vec!["first", "last"], texts(branch));
+
+    Ok(())
+}
+
+#[test]
+fn test_call_graph() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "call shop\n_: back home\n")?;
+    novel.add_scene(
+        "shop".into(),
+        r#"
+
+if gold < 5
+    return
+end
+_: buy
+
+    "#,
+    )?;
+    let (graph, root) = novel.extract_graph("test")?;
+
+    let mut children = graph.rev_neighbors(root);
+    let call = children.next().unwrap();
+    assert!(matches!(graph[call], GraphNode::Call(target) if target.scene == "shop"));
+    assert!(matches!(
+        graph[children.next().unwrap()],
+        GraphNode::Node { .. }
+    ));
+    assert_eq!(None, children.next());
+
+    // Both the early return and the end of the scene go back to the call
+    let returns = graph
+        .edges_directed(call, petgraph::Direction::Incoming)
+        .filter(|edge| *edge.weight() == novelscript::GraphEdge::Return)
+        .count();
+    assert_eq!(2, returns);
+
+    Ok(())
+}
+
+#[test]
+fn test_menu_graph() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+
+choice
+option "Go left"
+    _: went left
+option "Go right"
+    _: went right
+end
+
+    "#,
+    )?;
+    let (graph, root) = novel.extract_graph("test")?;
+
+    let menu = graph.rev_neighbors(root).next().unwrap();
+    assert!(matches!(graph[menu], GraphNode::Menu));
+    let options = graph
+        .rev_neighbors(menu)
+        .map(|node| match &graph[node] {
+            GraphNode::Option { choice, option } => {
+                match &graph[graph.rev_neighbors(node).next().unwrap()] {
+                    GraphNode::Node {
+                        node:
+                            novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+                                content,
+                                ..
+                            }),
+                    } => format!("{} {} => {}", choice, option.text, content),
+                    node => panic!("Unexpected option content {:?}", node),
+                }
+            }
+            node => panic!("Unexpected node {:?}", node),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec!["1 Go left => went left", "2 Go right => went right"],
+        options
+    );
+
+    Ok(())
+}
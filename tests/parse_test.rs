@@ -1,10 +1,8 @@
 use std::time::Instant;
 
-use novelscript;
-
 fn setup(s: &str) -> Result<novelscript::Novel, Box<dyn std::error::Error>> {
     let mut novel = novelscript::Novel::new();
-    novel.add_scene("test".into(), s);
+    novel.add_scene("test".into(), s)?;
     Ok(novel)
 }
 
@@ -29,7 +27,7 @@ end
         let before = Instant::now();
 
         for i in 0..UPPER {
-            novel.add_scene(format!("test-{}", i), data);
+            novel.add_scene(format!("test-{}", i), data)?;
         }
 
         before.elapsed().as_millis()
@@ -39,7 +37,7 @@ end
 
         for i in 0..UPPER {
             let mut state = novel.new_state(&format!("test-{}", i));
-            while let Some(_) = novel.next(&mut state) {}
+            while novel.next(&mut state)?.is_some() {}
         }
 
         before.elapsed().as_millis()
@@ -64,19 +62,19 @@ _: test
     let mut state = novel.new_state("test");
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("foo".into()),
             content: "test".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "test".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     Ok(())
@@ -96,19 +94,19 @@ foo: hmm... what if, you say test
     let mut state = novel.new_state("test");
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("foo".into()),
             content: "\"test\"".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "test? what!".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     Ok(())
@@ -130,13 +128,13 @@ end
     state.set_variable("num".into(), 13);
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "first".into()
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
-    assert_eq!(None, novel.next(&mut state));
+    assert_eq!(None, novel.next(&mut state)?);
 
     Ok(())
 }
@@ -156,7 +154,7 @@ end
 
     state.set_variable("num".into(), 13);
 
-    assert_eq!(None, novel.next(&mut state));
+    assert_eq!(None, novel.next(&mut state)?);
 
     Ok(())
 }
@@ -175,10 +173,10 @@ remove Foo
     state.set_variable("num".into(), 13);
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::RemoveCharacter {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::RemoveCharacter {
             name: "Foo".into()
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     Ok(())
@@ -204,20 +202,20 @@ end
     state.set_variable("num2".into(), 17);
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "first".into()
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "second".into()
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
-    assert_eq!(None, novel.next(&mut state));
+    assert_eq!(None, novel.next(&mut state)?);
 
     Ok(())
 }
@@ -240,21 +238,29 @@ end
     let mut state = novel.new_state("test");
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
-            "x".into(),
-            "y".into()
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
+            novelscript::ChoiceOption {
+                index: 1,
+                text: "x".into(),
+                previously_picked: false,
+            },
+            novelscript::ChoiceOption {
+                index: 2,
+                text: "y".into(),
+                previously_picked: false,
+            }
         ])),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     state.set_choice(1);
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "first".into()
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
-    assert_eq!(None, novel.next(&mut state));
+    assert_eq!(None, novel.next(&mut state)?);
 
     Ok(())
 }
@@ -278,29 +284,45 @@ end
     let mut state = novel.new_state("test");
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
-            "x".into(),
-            "y".into()
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
+            novelscript::ChoiceOption {
+                index: 1,
+                text: "x".into(),
+                previously_picked: false,
+            },
+            novelscript::ChoiceOption {
+                index: 2,
+                text: "y".into(),
+                previously_picked: false,
+            }
         ])),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     state.set_choice(1);
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "first".into()
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
-            "a".into(),
-            "b".into()
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
+            novelscript::ChoiceOption {
+                index: 1,
+                text: "a".into(),
+                previously_picked: false,
+            },
+            novelscript::ChoiceOption {
+                index: 2,
+                text: "b".into(),
+                previously_picked: false,
+            }
         ])),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     state.set_choice(1);
-    assert_eq!(None, novel.next(&mut state));
+    assert_eq!(None, novel.next(&mut state)?);
 
     Ok(())
 }
@@ -325,47 +347,47 @@ Foo: It is now night
     let mut state = novel.new_state("test");
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("Foo".into()),
             content: "Hello Bar".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::Character {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::Character {
             character: "Bar".into(),
             expression: Some("Normal".into()),
             placement: Some("Center".into()),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("Bar".into()),
             content: "Hello Foo".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::Background {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::Background {
             name: "Night".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::Character {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::Character {
             character: "Bar".into(),
             expression: Some("Cold".into()),
             placement: None,
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("Foo".into()),
             content: "It is now night".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     Ok(())
@@ -386,35 +408,35 @@ play test on music
     let mut state = novel.new_state("test");
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
             name: "test".into(),
             channel: "sfx".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
             name: "noise".into(),
             channel: "sfx".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
             name: "relax".into(),
             channel: "music".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
+        novelscript::SceneNodeUser::Load(novelscript::SceneNodeLoad::PlaySound {
             name: "test".into(),
             channel: "music".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     Ok(())
@@ -432,7 +454,7 @@ _: test
 jump test2
 
     "#,
-    );
+    )?;
     novel.add_scene(
         "test2".into(),
         r#"
@@ -441,40 +463,1067 @@ foo: it is test2
 _: indeed
 
     "#,
-    );
+    )?;
     let mut state = novel.new_state("test");
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("foo".into()),
             content: "test".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "test".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("foo".into()),
             content: "it is test2".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
     );
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: None,
             content: "indeed".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state)?.unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_span() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+foo: test
+if num = 13
+    _: first
+end
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+
+    state.set_variable("num".into(), 13);
+
+    novel.next(&mut state)?.unwrap();
+    assert_eq!(
+        Some(&novelscript::Span {
+            scene: "test".into(),
+            start: 1,
+            end: 10,
+            line: 2,
+            column: 1,
+        }),
+        novel.current_span(&state)?
+    );
+    novel.next(&mut state)?.unwrap();
+    let span = novel.current_span(&state)?.unwrap();
+    assert_eq!((4, 5), (span.line, span.column));
+
+    Ok(())
+}
+
+#[test]
+fn test_assignment() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+var trust = 0
+trust += 2
+trust = trust * 2 - 1
+if trust = 3
+    _: trusted
+end
+trust = -(trust + 1) * 3 % 5
+variable = trust / 2
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "trusted".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(None, novel.next(&mut state)?);
+    assert_eq!(Some(-2), state.get_int("trust"));
+    assert_eq!(Some(-1), state.get_int("variable"));
+
+    Ok(())
+}
+
+#[test]
+fn test_boolean_conditions() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+if (trust >= 5 and met_alice) or debug
+    _: first
+end
+if not (trust + 1) * 2 < 12 and not debug
+    _: second
+end
+if trust < 5 or (met_alice)
+    _: third
+end
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+
+    state.set_variable("trust".into(), 5);
+    state.set_variable("met_alice".into(), 0);
+    state.set_variable("debug".into(), 0);
+
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "second".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(None, novel.next(&mut state)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_else_if_chain() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+if num = 1
+    _: first
+else if num = 2
+    _: second
+else if num = 3
+    _: third
+else if num = 4
+    _: fourth
+else
+    _: other
+end
+
+    "#,
+    )?;
+
+    for (num, content) in [(1, "first"), (3, "third"), (4, "fourth"), (5, "other")] {
+        let mut state = novel.new_state("test");
+        state.set_variable("num".into(), num);
+        assert_eq!(
+            novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: content.into()
+            }),
+            novel.next(&mut state)?.unwrap()
+        );
+        assert_eq!(None, novel.next(&mut state)?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_variables() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+if route = "alice" and met_alice
+    _: first
+end
+var ratio = trust / 4.0
+var greeting = "Hello, " + name
+var debug = false
+if ratio >= 0.5 and not debug
+    _: second
+end
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+
+    state.set_variable("route".into(), "alice");
+    state.set_variable("met_alice".into(), true);
+    state.set_variable("trust".into(), 2);
+    state.set_variable("name".into(), "Bob".to_owned());
+
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "first".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "second".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(Some(0.5), state.get_float("ratio"));
+    assert_eq!(Some("Hello, Bob"), state.get_str("greeting"));
+    assert_eq!(Some(false), state.get_bool("debug"));
+    assert_eq!(
+        Some(&novelscript::Value::Bool(true)),
+        state.get_variable("met_alice")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_interpolation() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+Alice: Welcome back, {player_name}! You have {gold} gold.
+_: ({gold:>5}) ({ratio:.2}) ({player_name:-^9}) ({gold:03}) {{literal}}
+_: ({debt:05}) ({debt:0>5}) ({ratio:06.2})
+[Pay {price} gold / Leave]
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+
+    state.set_variable("player_name".into(), "Bob");
+    state.set_variable("gold".into(), 7);
+    state.set_variable("ratio".into(), 2.0 / 3.0);
+    state.set_variable("price".into(), 5);
+    state.set_variable("debt".into(), -3);
+
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: Some("Alice".into()),
+            content: "Welcome back, Bob! You have 7 gold.".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "(    7) (0.67) (---Bob---) (007) {literal}".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    // Only the zero flag pads after the sign, an explicit fill doesn't
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "(-0003) (000-3) (000.67)".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
+            novelscript::ChoiceOption {
+                index: 1,
+                text: "Pay 5 gold".into(),
+                previously_picked: false,
+            },
+            novelscript::ChoiceOption {
+                index: 2,
+                text: "Leave".into(),
+                previously_picked: false,
+            }
+        ])),
+        novel.next(&mut state)?.unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_labels() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+
+_: start
+if met
+    label inside
+    _: inside
+end
+count += 1
+if count < 2
+    jump inside
+end
+jump other.ending
+
+    "#,
+    )?;
+    novel.add_scene(
+        "other".into(),
+        r#"
+
+_: skipped
+label ending
+_: end
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+    state.set_variable("met".into(), false);
+    state.set_variable("count".into(), 0);
+
+    let mut lines = Vec::new();
+    while let Some(node) = novel.next(&mut state)? {
+        if let novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            content, ..
+        }) = node
+        {
+            lines.push(content);
+        }
+    }
+    assert_eq!(vec!["start", "inside", "end"], lines);
+    assert_eq!(Some(2), state.get_int("count"));
+
+    Ok(())
+}
+
+#[test]
+fn test_call_return() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene(
+        "test".into(),
+        r#"
+
+_: morning
+return: a character named return
+call shop
+_: noon
+call shop.closing
+_: night
+return
+_: unreachable
+
+    "#,
+    )?;
+    novel.add_scene(
+        "shop".into(),
+        r#"
+
+_: welcome
+if gold < 5
+    _: too poor
+    return
+end
+_: buy
+label closing
+_: goodbye
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+    state.set_variable("gold".into(), 0);
+
+    let mut lines = Vec::new();
+    while let Some(node) = novel.next(&mut state)? {
+        if let novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            content, ..
+        }) = node
+        {
+            lines.push(content);
+        }
+    }
+    assert_eq!(
+        vec![
+            "morning",
+            "a character named return",
+            "welcome",
+            "too poor",
+            "noon",
+            "goodbye",
+            "night"
+        ],
+        lines
+    );
+    assert_eq!(None, novel.next(&mut state)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_menu() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+choice
+option "Go left"
+    _: went left
+    choice
+    option "Open the door"
+        _: opened
+    option "Knock, {name}"
+        _: knocked
+    end
+option "Go right"
+    _: went right
+end
+_: done
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+    state.set_variable("name".into(), "Bob");
+
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
+            novelscript::ChoiceOption {
+                index: 1,
+                text: "Go left".into(),
+                previously_picked: false,
+            },
+            novelscript::ChoiceOption {
+                index: 2,
+                text: "Go right".into(),
+                previously_picked: false,
+            }
+        ])),
+        novel.next(&mut state)?.unwrap()
+    );
+    state.set_choice(1);
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "went left".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
+            novelscript::ChoiceOption {
+                index: 1,
+                text: "Open the door".into(),
+                previously_picked: false,
+            },
+            novelscript::ChoiceOption {
+                index: 2,
+                text: "Knock, Bob".into(),
+                previously_picked: false,
+            }
+        ])),
+        novel.next(&mut state)?.unwrap()
+    );
+    state.set_choice(2);
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "knocked".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "done".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(None, novel.next(&mut state)?);
+    assert_eq!(None, state.get_variable("choice"));
+
+    Ok(())
+}
+
+#[test]
+fn test_choice_plain_words() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+label hub
+[Ask if she is ok / Ask if ready / once more / Leave]
+if choice != 4
+    jump hub
+end
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+
+    // Only `{if ...}` and `{once}` are guards and flags, the plain words are text
+    let choice = |picked: bool| {
+        let options = ["Ask if she is ok", "Ask if ready", "once more", "Leave"];
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(
+            (1..)
+                .zip(options)
+                .map(|(index, text)| novelscript::ChoiceOption {
+                    index,
+                    text: text.into(),
+                    previously_picked: picked && index == 3,
+                })
+                .collect(),
+        ))
+    };
+    assert_eq!(choice(false), novel.next(&mut state)?.unwrap());
+    state.set_choice(3);
+    assert_eq!(choice(true), novel.next(&mut state)?.unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn test_conditional_options() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+label hub
+[{once} Look around / Ask about the key {if has_key and not asked} / Leave]
+if choice = 1
+    has_key = true
+end
+if choice = 2
+    asked = true
+end
+if choice != 3
+    jump hub
+end
+choice
+option once "Say goodbye"
+    _: bye
+option "Stay" if false
+end
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+    state.set_variable("has_key".into(), false);
+    state.set_variable("asked".into(), false);
+
+    let option = |index: i32, text: &str| novelscript::ChoiceOption {
+        index,
+        text: text.into(),
+        previously_picked: false,
+    };
+    let choice =
+        |options| novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(options));
+
+    assert_eq!(
+        choice(vec![option(1, "Look around"), option(3, "Leave")]),
+        novel.next(&mut state)?.unwrap()
+    );
+    state.set_choice(1);
+    assert_eq!(
+        choice(vec![option(2, "Ask about the key"), option(3, "Leave")]),
+        novel.next(&mut state)?.unwrap()
+    );
+    state.set_choice(1);
+    assert_eq!(
+        Err(novelscript::NovelError::UnavailableChoice(1)),
+        novel.next(&mut state)
+    );
+    state.set_choice(2);
+    assert_eq!(
+        choice(vec![option(3, "Leave")]),
+        novel.next(&mut state)?.unwrap()
+    );
+    state.set_choice(3);
+    assert_eq!(
+        choice(vec![option(1, "Say goodbye")]),
+        novel.next(&mut state)?.unwrap()
+    );
+    state.set_choice(1);
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "bye".into()
+        }),
+        novel.next(&mut state)?.unwrap()
+    );
+    assert_eq!(None, novel.next(&mut state)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_choice_history() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+label intro
+choice intro_menu
+option "Tea"
+    _: tea
+option "Coffee"
+    _: coffee
+end
+if chose intro_menu 1 and not visited
+    visited = true
+    jump intro
+end
+[Bye / Stay]
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+    state.set_variable("visited".into(), false);
+
+    novel.next(&mut state)?;
+    state.set_choice(1);
+    novel.next(&mut state)?;
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Choice(vec![
+            novelscript::ChoiceOption {
+                index: 1,
+                text: "Tea".into(),
+                previously_picked: true,
+            },
+            novelscript::ChoiceOption {
+                index: 2,
+                text: "Coffee".into(),
+                previously_picked: false,
+            }
+        ])),
+        novel.next(&mut state)?.unwrap()
+    );
+    state.set_choice(2);
+    novel.next(&mut state)?;
+    novel.next(&mut state)?;
+    state.set_choice(1);
+    assert_eq!(None, novel.next(&mut state)?);
+
+    assert_eq!(
+        &[
+            novelscript::ChoiceRecord {
+                id: "intro_menu".into(),
+                shown: vec![1, 2],
+                picked: 1,
+            },
+            novelscript::ChoiceRecord {
+                id: "intro_menu".into(),
+                shown: vec![1, 2],
+                picked: 2,
+            },
+            novelscript::ChoiceRecord {
+                id: "test:2ca6e04d554af45d".into(),
+                shown: vec![1, 2],
+                picked: 1,
+            }
+        ],
+        state.choice_history()
+    );
+    assert_eq!(Some(2), state.last_choice("intro_menu"));
+    assert!(state.has_picked("intro_menu", 1));
+
+    // Choices without an id keep it when the script is edited around them
+    let edited = setup(
+        "_: hello
+
+[Bye / Stay]
+",
+    )?;
+    let mut state = edited.new_state("test");
+    edited.next(&mut state)?;
+    edited.next(&mut state)?;
+    state.set_choice(1);
+    edited.next(&mut state)?;
+    assert_eq!("test:2ca6e04d554af45d", state.choice_history()[0].id);
+
+    Ok(())
+}
+
+#[test]
+fn test_rollback() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = setup(
+        r#"
+
+_: start
+choice menu
+option "Left"
+    gold += 1
+    _: left
+option "Right"
+    _: right
+end
+_: done
+
+    "#,
+    )?;
+    novel.set_rollback_depth(2);
+    let mut state = novel.new_state("test");
+    state.set_variable("gold".into(), 0);
+
+    let text = |content: &str| {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: content.into(),
+        })
+    };
+
+    assert_eq!(text("start"), novel.next(&mut state)?.unwrap());
+    let menu = novel.next(&mut state)?.unwrap();
+    state.set_choice(1);
+    assert_eq!(text("left"), novel.next(&mut state)?.unwrap());
+    assert_eq!(text("done"), novel.next(&mut state)?.unwrap());
+    assert_eq!(Some(1), state.get_int("gold"));
+    assert_eq!(2, state.rollback_len());
+
+    assert_eq!(menu, novel.rollback(&mut state, 2)?.unwrap());
+    assert_eq!(Some(0), state.get_int("gold"));
+    assert_eq!(None, state.last_choice("menu"));
+    assert_eq!(0, state.rollback_len());
+    assert_eq!(
+        Err(novelscript::NovelError::RollbackTooFar {
+            steps: 1,
+            available: 0
+        }),
+        novel.rollback(&mut state, 1)
+    );
+
+    state.set_choice(2);
+    assert_eq!(text("right"), novel.next(&mut state)?.unwrap());
+    assert_eq!(text("done"), novel.next(&mut state)?.unwrap());
+    assert_eq!(Some(0), state.get_int("gold"));
+    assert_eq!(Some(2), state.last_choice("menu"));
+
+    Ok(())
+}
+
+#[test]
+fn test_rollback_over_loads() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = setup("_: a\nscene Park\n_: b\n")?;
+    novel.set_backlog_size(1);
+    let mut state = novel.new_state("test");
+
+    novel.next(&mut state)?;
+    novel.next(&mut state)?;
+    novel.next(&mut state)?;
+    assert_eq!(1, state.rollback_len());
+    assert_eq!(Some("Park"), state.presentation().background.as_deref());
+
+    // Only the lines the player saw are stepped back over
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "a".into(),
+        }),
+        novel.rollback(&mut state, 1)?.unwrap()
+    );
+    assert_eq!(None, state.presentation().background);
+    assert!(state.backlog().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_backlog() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = setup(
+        r#"
+
+Alice: Hello, {name}.
+_: first
+[Wave / Leave]
+_: second
+
+    "#,
+    )?;
+    novel.set_backlog_size(3);
+    let mut state = novel.new_state("test");
+    state.set_variable("name".into(), "Bob");
+
+    novel.next(&mut state)?;
+    assert_eq!(
+        vec![&novelscript::BacklogEntry::Line {
+            speaker: Some("Alice".into()),
+            content: "Hello, Bob.".into(),
+            scene: "test".into(),
+            line: 3,
+        }],
+        state.backlog().iter().collect::<Vec<_>>()
+    );
+
+    novel.next(&mut state)?;
+    novel.next(&mut state)?;
+    state.set_choice(1);
+    novel.next(&mut state)?;
+    assert_eq!(
+        vec![
+            &novelscript::BacklogEntry::Line {
+                speaker: None,
+                content: "first".into(),
+                scene: "test".into(),
+                line: 4,
+            },
+            &novelscript::BacklogEntry::Choice {
+                id: "test:1aee0dfefd7f5a14".into(),
+                index: 1,
+                text: "Wave".into(),
+            },
+            &novelscript::BacklogEntry::Line {
+                speaker: None,
+                content: "second".into(),
+                scene: "test".into(),
+                line: 6,
+            }
+        ],
+        state.backlog().iter().collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_read_set() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+_: first
+scene Park
+_: Hi {name}
+
+    "#,
+    )?;
+    let mut read_set = novelscript::ReadSet::new();
+
+    let mut state = novel.new_state("test");
+    state.set_variable("name".into(), "Bob");
+    let mut read = Vec::new();
+    while let Some((_, was_read)) = novel.next_with_read_set(&mut state, &mut read_set)? {
+        read.push(was_read);
+    }
+    assert_eq!(vec![false, false, false], read);
+    assert_eq!(2, read_set.len());
+
+    // Lines stay read when the script around them changes
+    let novel = setup(
+        r#"
+
+_: new
+_: first
+_: Hi {name}
+
+    "#,
+    )?;
+    let mut state = novel.new_state("test");
+    state.set_variable("name".into(), "Alice");
+    let mut read = Vec::new();
+    while let Some((_, was_read)) = novel.next_with_read_set(&mut state, &mut read_set)? {
+        read.push(was_read);
+    }
+    assert_eq!(vec![false, true, true], read);
+    assert_eq!(3, read_set.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_presentation() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+scene Room
+load Foo { expression happy placement left }
+load Bar { }
+play bgm on music
+_: hello
+set Foo expression sad
+remove Bar
+play night on music
+scene Night
+_: later
+
+        "#,
+    )?;
+
+    let mut state = novel.new_state("test");
+    while novel.next(&mut state)?
+        != Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "hello".into(),
+            },
+        ))
+    {}
+    let presentation = state.presentation();
+    assert_eq!(Some("Room"), presentation.background.as_deref());
+    assert_eq!(
+        vec!["Foo", "Bar"],
+        presentation
+            .characters
+            .iter()
+            .map(|character| character.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!("bgm", presentation.sounds["music"]);
+
+    while novel.next(&mut state)?.is_some() {}
+    let presentation = state.presentation();
+    assert_eq!(Some("Night"), presentation.background.as_deref());
+    assert_eq!(
+        vec![novelscript::Character {
+            name: "Foo".into(),
+            expression: Some("sad".into()),
+            placement: Some("left".into()),
+        }],
+        presentation.characters
+    );
+    assert_eq!("night", presentation.sounds["music"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_next_blocking() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+scene Room
+play bgm on music
+_: hello
+load Foo { }
+[a / b]
+
+        "#,
+    )?;
+
+    let mut state = novel.new_state("test");
+    assert_eq!(
+        novelscript::Blocking {
+            loads: vec![
+                novelscript::SceneNodeLoad::Background {
+                    name: "Room".into()
+                },
+                novelscript::SceneNodeLoad::PlaySound {
+                    name: "bgm".into(),
+                    channel: "music".into()
+                },
+            ],
+            node: Some(novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "hello".into()
+            }),
+        },
+        novel.next_blocking(&mut state)?
+    );
+    let blocking = novel.next_blocking(&mut state)?;
+    assert_eq!(1, blocking.loads.len());
+    assert!(matches!(
+        blocking.node,
+        Some(novelscript::SceneNodeData::Choice(_))
+    ));
+
+    state.set_choice(1);
+    assert_eq!(
+        novelscript::Blocking {
+            loads: Vec::new(),
+            node: None
+        },
+        novel.next_blocking(&mut state)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_skip_to_choice() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+scene Room
+load Foo { }
+play bgm on music
+_: hello
+scene Night
+remove Foo
+play night on music
+_: later
+[a / b]
+_: after
+
+        "#,
+    )?;
+
+    let mut state = novel.new_state("test");
+    let skipped = novel.skip_to_choice(&mut state)?;
+    assert_eq!(Some("Night"), skipped.presentation.background.as_deref());
+    assert!(skipped.presentation.characters.is_empty());
+    assert_eq!("night", skipped.presentation.sounds["music"]);
+    assert_eq!(2, skipped.choice.unwrap().len());
+
+    state.set_choice(2);
+    assert_eq!(None, novel.skip_to_choice(&mut state)?.choice);
+
+    Ok(())
+}
+
+#[test]
+fn test_choice_reset() -> Result<(), Box<dyn std::error::Error>> {
+    let novel = setup(
+        r#"
+
+choice a
+option "a1"
+    _: a1
+option "a2"
+    _: a2
+end
+choice b
+option "b1"
+    _: b1
+option "b2"
+    _: b2
+end
+
+        "#,
+    )?;
+
+    let mut state = novel.new_state("test");
+    novel.next(&mut state)?;
+    state.set_choice(2);
+    novel.next(&mut state)?;
+    novel.next(&mut state)?;
+    // The pick of the first menu isn't reused for the second one
+    assert_eq!(
+        Err(novelscript::NovelError::InvalidChoice {
+            choice: 0,
+            options: 2
+        }),
+        novel.next(&mut state)
+    );
+    assert_eq!(1, state.choice_history().len());
+
+    // A bracket choice can still be passed without a pick, `choice` is then 0
+    let novel = setup(
+        r#"
+
+[x / y]
+[a / b]
+if choice = 0
+    _: no pick
+end
+
+        "#,
+    )?;
+    let mut state = novel.new_state("test");
+    novel.next(&mut state)?;
+    state.set_choice(2);
+    novel.next(&mut state)?;
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "no pick".into()
+            }
+        )),
+        novel.next(&mut state)?
     );
+    assert_eq!(1, state.choice_history().len());
+    // `choice` is read from the scope, it never becomes a variable
+    assert_eq!(None, state.get_variable("choice"));
 
     Ok(())
 }
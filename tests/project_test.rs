@@ -0,0 +1,218 @@
+use std::path::{Path, PathBuf};
+
+/// A directory under the temp dir that is removed again when the test is done.
+struct Project(PathBuf);
+
+impl std::ops::Deref for Project {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for Project {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes `files` into a fresh directory under the temp dir.
+fn project(name: &str, files: &[(&str, &str)]) -> std::io::Result<Project> {
+    let dir = std::env::temp_dir().join(format!("novelscript-{}-{}", name, std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    for (path, data) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, data)?;
+    }
+    Ok(Project(dir))
+}
+
+#[test]
+fn test_load_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = project(
+        "load_dir",
+        &[
+            ("intro.ns", "_: intro\njump chapter1/forest\n"),
+            ("chapter1/forest.ns", "_: forest\n"),
+            ("notes.txt", "not a script"),
+        ],
+    )?;
+    let mut novel = novelscript::Novel::new();
+    novel.load_dir(&dir)?;
+
+    let mut state = novel.new_state("intro");
+    novel.next(&mut state)?;
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "forest".into(),
+            }
+        )),
+        novel.next(&mut state)?
+    );
+
+    // Loading the same scenes again would overwrite them
+    assert!(matches!(
+        novel.load_dir(&dir),
+        Err(novelscript::LoadError::DuplicateScene(name)) if name == "chapter1/forest"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_load_dir_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = project(
+        "load_dir_errors",
+        &[("intro.ns", "_: intro\n"), ("intro.v2.ns", "_: intro\n")],
+    )?;
+    let mut novel = novelscript::Novel::new();
+    assert!(matches!(
+        novel.load_dir(&dir),
+        Err(novelscript::LoadError::InvalidSceneName(name)) if name == "intro.v2"
+    ));
+    assert!(matches!(
+        novel.add_source(novelscript::DirSource::new(&*dir)),
+        Err(novelscript::LoadError::InvalidSceneName(_))
+    ));
+    // The scene before the invalid one wasn't added either
+    assert!(novel.add_scene("intro".into(), "_: intro\n").is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_load_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = project(
+        "load_manifest",
+        &[
+            (
+                "novel.json",
+                r#"{ "start": "intro", "scenes": ["intro.ns"], "variables": { "gold": 10 } }"#,
+            ),
+            ("intro.ns", "_: You have {gold} gold\n"),
+            ("unused.ns", "_: unused\n"),
+        ],
+    )?;
+    let mut novel = novelscript::Novel::new();
+    let manifest = novel.load_manifest(dir.join("novel.json"))?;
+
+    let mut state = manifest.new_state(&novel);
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "You have 10 gold".into(),
+            }
+        )),
+        novel.next(&mut state)?
+    );
+    assert_eq!(
+        Err(novelscript::NovelError::SceneNotFound("unused".into())),
+        novel.validate_state(&novel.new_state("unused"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_manifest_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = project(
+        "manifest_errors",
+        &[
+            ("novel.json", r#"{ "start": "missing" }"#),
+            ("intro.ns", "_: intro\n"),
+        ],
+    )?;
+    let err = novelscript::Novel::new()
+        .load_manifest(dir.join("novel.json"))
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with("novel.json': the start scene 'missing' doesn't exist"));
+
+    let err = novelscript::Novel::new()
+        .load_manifest(dir.join("missing.json"))
+        .unwrap_err();
+    assert!(matches!(err, novelscript::LoadError::Io { .. }));
+
+    // A failed load adds none of the scenes
+    let mut novel = novelscript::Novel::new();
+    assert!(novel.load_manifest(dir.join("novel.json")).is_err());
+    assert!(novel.add_scene("intro".into(), "_: intro\n").is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_lazy_source() -> Result<(), Box<dyn std::error::Error>> {
+    let mut source = novelscript::MemorySource::new();
+    source.insert("intro".into(), "_: intro\njump broken\n".into());
+    source.insert("broken".into(), "if\n".into());
+    let mut novel = novelscript::Novel::new();
+    novel.add_source(source)?;
+
+    // The broken scene is only parsed once it's jumped to
+    let mut state = novel.new_state("intro");
+    novel.next(&mut state)?;
+    assert!(matches!(
+        novel.next(&mut state),
+        Err(novelscript::NovelError::SceneParse(err)) if err.location().scene == "broken"
+            && err.location().line == 1
+    ));
+    assert!(matches!(
+        novelscript::ScriptSource::read(&novelscript::MemorySource::new(), "missing"),
+        Err(novelscript::LoadError::SceneNotFound(scene)) if scene == "missing"
+    ));
+    assert!(matches!(
+        novel.extract_graph("missing"),
+        Err(novelscript::NovelError::SceneNotFound(scene)) if scene == "missing"
+    ));
+
+    let dir = project("lazy_source", &[("chapter1/forest.ns", "_: forest\n")])?;
+    novel.add_source(novelscript::DirSource::new(&*dir))?;
+    assert!(novel.extract_graph("chapter1/forest").is_ok());
+    assert!(matches!(
+        novel.add_source(novelscript::DirSource::new(&*dir)),
+        Err(novelscript::LoadError::DuplicateScene(_))
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_zip_source() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    writer.start_file("chapter1/forest.ns", zip::write::FileOptions::default())?;
+    writer.write_all(b"_: forest\n")?;
+    let archive = writer.finish()?;
+
+    let mut novel = novelscript::Novel::new();
+    novel.add_source(novelscript::ZipSource::new(archive)?)?;
+    let mut state = novel.new_state("chapter1/forest");
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "forest".into(),
+            }
+        )),
+        novel.next(&mut state)?
+    );
+
+    Ok(())
+}
@@ -10,24 +10,24 @@ fn test_save_load() {
     let serialized = {
         let mut novel = novelscript::Novel::new();
 
-        novel.add_scene("test".into(), s);
+        novel.add_scene("test".into(), s).unwrap();
 
         let mut state = novel.new_state("test");
 
         assert_eq!(
-            &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
                 speaker: Some("foo".into()),
                 content: "test".into(),
             }),
-            novel.next(&mut state).unwrap()
+            novel.next(&mut state).unwrap().unwrap()
         );
 
         assert_eq!(
-            &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
                 speaker: None,
                 content: "test".into(),
             }),
-            novel.next(&mut state).unwrap()
+            novel.next(&mut state).unwrap().unwrap()
         );
 
         serde_json::to_string(&state).unwrap()
@@ -37,13 +37,381 @@ fn test_save_load() {
 
     let mut novel = novelscript::Novel::new();
 
-    novel.add_scene("test".into(), s);
+    novel.add_scene("test".into(), s).unwrap();
 
     assert_eq!(
-        &novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
             speaker: Some("bar".into()),
             content: "test".into(),
         }),
-        novel.next(&mut state).unwrap()
+        novel.next(&mut state).unwrap().unwrap()
     );
 }
+
+#[test]
+fn test_save_load_variables() {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "_: test").unwrap();
+
+    let mut state = novel.new_state("test");
+    state.set_variable("int".into(), 3);
+    state.set_variable("float".into(), 3.0);
+    state.set_variable("bool".into(), true);
+    state.set_variable("string".into(), "three");
+
+    let serialized = serde_json::to_string(&state).unwrap();
+    let state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+
+    assert_eq!(Some(&novelscript::Value::Int(3)), state.get_variable("int"));
+    assert_eq!(
+        Some(&novelscript::Value::Float(3.0)),
+        state.get_variable("float")
+    );
+    assert_eq!(Some(true), state.get_bool("bool"));
+    assert_eq!(Some("three"), state.get_str("string"));
+}
+
+#[test]
+fn test_save_load_call_stack() {
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene("test".into(), "call shop\n_: back home\n")
+        .unwrap();
+    novel.add_scene("shop".into(), "_: welcome\n").unwrap();
+
+    let mut state = novel.new_state("test");
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "welcome".into(),
+        }),
+        novel.next(&mut state).unwrap().unwrap()
+    );
+
+    let serialized = serde_json::to_string(&state).unwrap();
+    let mut state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "back home".into(),
+        }),
+        novel.next(&mut state).unwrap().unwrap()
+    );
+}
+
+#[test]
+fn test_save_load_choice_history() {
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene(
+            "test".into(),
+            "choice menu [a / b]\nif chose menu 2\n    _: b\nend\n",
+        )
+        .unwrap();
+
+    let mut state = novel.new_state("test");
+    novel.next(&mut state).unwrap();
+    state.set_choice(2);
+    novel.next(&mut state).unwrap();
+
+    let serialized = serde_json::to_string(&state).unwrap();
+    let state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(Some(2), state.last_choice("menu"));
+    assert_eq!(1, state.choice_history().len());
+}
+
+#[test]
+fn test_save_load_rollback() {
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene("test".into(), "_: first\n_: second\n")
+        .unwrap();
+
+    let mut state = novel.new_state("test");
+    novel.next(&mut state).unwrap();
+    novel.next(&mut state).unwrap();
+
+    let serialized = serde_json::to_string(&state).unwrap();
+    let mut state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "first".into(),
+        }),
+        novel.rollback(&mut state, 1).unwrap().unwrap()
+    );
+}
+
+#[test]
+fn test_save_load_backlog() {
+    let mut novel = novelscript::Novel::new();
+    novel.set_backlog_size(10);
+    novel.add_scene("test".into(), "_: first\n").unwrap();
+
+    let mut state = novel.new_state("test");
+    novel.next(&mut state).unwrap();
+
+    let serialized = serde_json::to_string(&state).unwrap();
+    let state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(1, state.backlog().len());
+}
+
+#[test]
+fn test_save_load_read_set() {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "_: first\n").unwrap();
+
+    let mut read_set = novelscript::ReadSet::new();
+    let mut state = novel.new_state("test");
+    novel.next_with_read_set(&mut state, &mut read_set).unwrap();
+
+    let serialized = serde_json::to_string(&read_set).unwrap();
+    let mut read_set: novelscript::ReadSet = serde_json::from_str(&serialized).unwrap();
+
+    let mut state = novel.new_state("test");
+    let (_, was_read) = novel
+        .next_with_read_set(&mut state, &mut read_set)
+        .unwrap()
+        .unwrap();
+    assert!(was_read);
+}
+
+#[test]
+fn test_save_load_relocate() {
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene(
+            "test".into(),
+            "_: first\nif true\n    _: second\n    #third _: third\nend\n_: last\n",
+        )
+        .unwrap();
+
+    let mut state = novel.new_state("test");
+    novel.next(&mut state).unwrap();
+    novel.next(&mut state).unwrap();
+    let serialized = serde_json::to_string(&state).unwrap();
+
+    // Lines added above and a tagged line that was rewritten
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene(
+            "test".into(),
+            "_: new\n_: first\nif true\n    _: added\n    _: second\n    #third _: third, rewritten\nend\n_: last\n",
+        )
+        .unwrap();
+    let mut state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+    novel.relocate(&mut state).unwrap();
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "second".into(),
+        }),
+        novel.current(&mut state).unwrap().unwrap()
+    );
+    assert_eq!(
+        novelscript::SceneNodeUser::Data(novelscript::SceneNodeData::Text {
+            speaker: None,
+            content: "third, rewritten".into(),
+        }),
+        novel.next(&mut state).unwrap().unwrap()
+    );
+
+    // The line the save was at is gone
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene("test".into(), "_: first\nif true\n    _: changed\nend\n")
+        .unwrap();
+    let mut state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+    assert!(matches!(
+        novel.relocate(&mut state),
+        Err(novelscript::NovelError::IncompatibleState { scene, .. }) if scene == "test"
+    ));
+}
+
+#[test]
+fn test_load_state_migration() {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "_: first\n").unwrap();
+    let mut state = novel.new_state("test");
+    state.set_variable("gold".into(), 5);
+    novel.next(&mut state).unwrap();
+    let serialized = serde_json::to_string(&state).unwrap();
+
+    // The next release renamed gold to coins
+    novel.set_version(1);
+    novel.add_migration(0, |state| {
+        if let Some(gold) = state.remove_variable("gold") {
+            state.set_variable("coins".into(), gold);
+        }
+        Ok(())
+    });
+    let state = novel
+        .load_state(serde_json::from_str(&serialized).unwrap())
+        .unwrap();
+    assert_eq!(1, state.version());
+    assert_eq!(None, state.get_int("gold"));
+    assert_eq!(Some(5), state.get_int("coins"));
+
+    // Saves from a newer release can't be loaded by an older one
+    let serialized = serde_json::to_string(&state).unwrap();
+    novel.set_version(0);
+    assert_eq!(
+        Err(novelscript::NovelError::NewerVersion {
+            found: 1,
+            current: 0
+        }),
+        novel
+            .load_state(serde_json::from_str(&serialized).unwrap())
+            .map(|_| ())
+    );
+
+    let mut value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+    value["format"] = 99.into();
+    assert_eq!(
+        Err(novelscript::NovelError::UnsupportedFormat {
+            found: 99,
+            supported: novelscript::STATE_FORMAT
+        }),
+        novel
+            .load_state(serde_json::from_value(value).unwrap())
+            .map(|_| ())
+    );
+}
+
+#[test]
+fn test_migrate_scene_rename() -> Result<(), Box<dyn std::error::Error>> {
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("intro".into(), "_: first\n_: second\n")?;
+    let mut state = novel.new_state("intro");
+    novel.next(&mut state)?;
+    let serialized = serde_json::to_string(&state)?;
+
+    // The next release renamed the scene and added a line to it
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("prologue".into(), "_: new\n_: first\n_: second\n")?;
+    novel.set_version(1);
+    novel.add_migration(0, |state| {
+        state.rename_scene("intro", "prologue");
+        Ok(())
+    });
+    let mut state = novel.load_state(serde_json::from_str(&serialized)?)?;
+    assert_eq!("prologue", state.scene());
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "second".into(),
+            }
+        )),
+        novel.next(&mut state)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_state() {
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene("test".into(), "if true\n    _: first\nend\n")
+        .unwrap();
+    let mut state = novel.new_state("test");
+    novel.next(&mut state).unwrap();
+    assert_eq!(Ok(()), novel.validate_state(&state));
+    let serialized = serde_json::to_string(&state).unwrap();
+    let state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+
+    // The if statement the state is inside of became a line
+    let mut novel = novelscript::Novel::new();
+    novel.add_scene("test".into(), "_: first\n").unwrap();
+    assert_eq!(
+        Err(novelscript::NovelError::InvalidScope {
+            scene: "test".into(),
+            depth: 0
+        }),
+        novel.validate_state(&state)
+    );
+
+    // The scene got shorter than the line the state is at
+    let mut longer = novelscript::Novel::new();
+    longer
+        .add_scene(
+            "test".into(),
+            "_: first
+_: second
+_: third
+",
+        )
+        .unwrap();
+    let mut at_end = longer.new_state("test");
+    while longer.next(&mut at_end).unwrap().is_some() {}
+    assert_eq!(None, longer.next(&mut at_end).unwrap());
+    assert_eq!(Ok(()), longer.validate_state(&at_end));
+    assert_eq!(
+        Err(novelscript::NovelError::InvalidScope {
+            scene: "test".into(),
+            depth: 0
+        }),
+        novel.validate_state(&at_end)
+    );
+
+    let novel = novelscript::Novel::new();
+    assert_eq!(
+        Err(novelscript::NovelError::SceneNotFound("test".into())),
+        novel.validate_state(&state)
+    );
+}
+
+#[test]
+fn test_save_load_presentation() {
+    let mut novel = novelscript::Novel::new();
+    novel
+        .add_scene("test".into(), "scene Room\nload Foo { }\n_: hello\n")
+        .unwrap();
+
+    let mut state = novel.new_state("test");
+    novel.next(&mut state).unwrap();
+    novel.next(&mut state).unwrap();
+
+    let serialized = serde_json::to_string(&state).unwrap();
+    let state: novelscript::NovelState = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(Some("Room"), state.presentation().background.as_deref());
+    assert!(state.presentation().character("Foo").is_some());
+}
+
+#[test]
+fn test_save_load_added_nodes() -> Result<(), Box<dyn std::error::Error>> {
+    let text = |content: &str| novelscript::SceneNode {
+        kind: novelscript::SceneNodeKind::User(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: content.into(),
+            },
+        )),
+        span: novelscript::Span::default(),
+        id: String::new(),
+    };
+    let mut novel = novelscript::Novel::new();
+    novel.add_nodes("test".into(), vec![text("one"), text("two")])?;
+    let mut state = novel.new_state("test");
+    novel.next(&mut state)?;
+    novel.next(&mut state)?;
+    let serialized = serde_json::to_string(&state)?;
+
+    // Nodes built by hand get ids too, so the save finds its line again
+    let mut novel = novelscript::Novel::new();
+    novel.add_nodes("test".into(), vec![text("new"), text("one"), text("two")])?;
+    let mut state = novel.load_state(serde_json::from_str(&serialized)?)?;
+    assert_eq!(
+        Some(novelscript::SceneNodeUser::Data(
+            novelscript::SceneNodeData::Text {
+                speaker: None,
+                content: "two".into(),
+            }
+        )),
+        novel.current(&mut state)?
+    );
+
+    Ok(())
+}